@@ -1,17 +1,23 @@
-use alphgen;
+use alphgen::{Anchor, FontBuilder};
 
 fn main() {
     let width = 8;
     let height = 8;
-    let ligatures = Vec::new();
 
-    let glyphs = GLYPHS.into_iter().copied();
-    let font = alphgen::bitmap_font(width, height, MISSING_GLYPH, glyphs, ligatures)
+    let glyphs = GLYPHS.iter().copied();
+    let font = FontBuilder::new(width, height, MISSING_GLYPH)
+        .glyphs(glyphs)
+        .mark('\u{0300}', GRAVE, Anchor { x: 3, y: 6 })
+        .mark('\u{0301}', ACUTE, Anchor { x: 3, y: 6 })
+        .build()
         .unwrap();
     font.save("my_neat_font.ttf")
         .unwrap();
 }
 
+const GRAVE: &[u8] = &0x1008000000000000u64.to_be_bytes();
+const ACUTE: &[u8] = &0x0810000000000000u64.to_be_bytes();
+
 const MISSING_GLYPH: &[u8] = &0xff99a589918191ffu64.to_be_bytes();
 const GLYPHS: &[(char, &[u8])] = &[
     (' ', &0x0000000000000000u64.to_be_bytes()),
@@ -0,0 +1,2677 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use crate::{Bitmap, Font, GlyphId, Rect};
+use crate::error::Error;
+use crate::fea::FeatureFile;
+use crate::sprite::Sprite;
+use crate::tables::{
+    ChainRule, CMap, Component, CursiveConnection, FeatureParams, GDef, Glyf, Glyph, GlyphClass,
+    GPos, GPosBuilder, GSub, GSubBuilder, Head, HHea, HMtx, Kern, LayoutBuilder, Ligature,
+    LookupFlags, LookupId, LookupSubtable, MarkAttachment, Name, Os2, PairClasses, PosSubtable,
+    Post, SubstSubtable,
+};
+use crate::tables::name::*;
+use crate::tables::post::{glyph_name, is_valid_glyph_name};
+use crate::unicode;
+
+/// A point on a sprite's pixel grid, measured in pixels from the bottom
+/// left corner of the sprite, with y pointing up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Anchor {
+    pub x: i16,
+    pub y: i16,
+}
+
+/// A reference to a glyph in the font: either the glyph a character
+/// maps to, or a glyph by its (PostScript) name.
+///
+/// Encoded glyphs may be referred to by name as well, eg "a", "zero",
+/// or "uni015D".
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GlyphRef {
+    Char(char),
+    Name(String),
+}
+
+impl From<char> for GlyphRef {
+    fn from(chr: char) -> Self {
+        GlyphRef::Char(chr)
+    }
+}
+
+impl From<&str> for GlyphRef {
+    fn from(name: &str) -> Self {
+        GlyphRef::Name(name.to_string())
+    }
+}
+
+impl From<String> for GlyphRef {
+    fn from(name: String) -> Self {
+        GlyphRef::Name(name)
+    }
+}
+
+impl fmt::Display for GlyphRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlyphRef::Char(chr) => write!(f, "{chr:?}"),
+            GlyphRef::Name(name) => write!(f, "/{name}"),
+        }
+    }
+}
+
+/// A contextual substitution: a sequence of input glyphs, some of which
+/// are substituted when the sequence is preceded by the `backtrack`
+/// glyphs and followed by the `lookahead` glyphs.
+///
+/// Each position in the rule matches any one of a class of glyphs.
+/// Positions are added in reading order.
+#[derive(Clone, Debug, Default)]
+pub struct ContextualRule {
+    backtrack: Vec<Vec<GlyphRef>>,
+    input: Vec<InputPosition>,
+    lookahead: Vec<Vec<GlyphRef>>,
+}
+
+#[derive(Clone, Debug)]
+struct InputPosition {
+    class: Vec<GlyphRef>,
+    substitutions: Vec<Substitution>,
+}
+
+impl ContextualRule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require a glyph from `class` before the input.
+    pub fn backtrack<I, G>(mut self, class: I) -> Self
+    where
+        I: IntoIterator<Item=G>,
+        G: Into<GlyphRef>,
+    {
+        self.backtrack.push(class.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Match a glyph from `class` in the input, leaving it as it is.
+    pub fn input<I, G>(mut self, class: I) -> Self
+    where
+        I: IntoIterator<Item=G>,
+        G: Into<GlyphRef>,
+    {
+        let class = class.into_iter().map(Into::into).collect();
+        self.input.push(InputPosition { class, substitutions: Vec::new() });
+        self
+    }
+
+    /// Match any glyph in `substitutions` in the input, and replace it
+    /// with its substitute.
+    pub fn substitute<I, A, B>(mut self, substitutions: I) -> Self
+    where
+        I: IntoIterator<Item=(A, B)>,
+        A: Into<GlyphRef>,
+        B: Into<GlyphRef>,
+    {
+        let substitutions: Vec<_> = substitutions.into_iter()
+            .map(|(from, to)| (from.into(), to.into()))
+            .collect();
+        let class = substitutions.iter().map(|(from, _)| from.clone()).collect();
+        self.input.push(InputPosition { class, substitutions });
+        self
+    }
+
+    /// Require a glyph from `class` after the input.
+    pub fn lookahead<I, G>(mut self, class: I) -> Self
+    where
+        I: IntoIterator<Item=G>,
+        G: Into<GlyphRef>,
+    {
+        self.lookahead.push(class.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+/// The glyph for `chr` cycled to its `idx`th form: 0 for its default
+/// glyph, otherwise its alternate, eg "a.alt1".
+fn cycled_form(chr: char, idx: usize) -> GlyphRef {
+    if idx == 0 { chr.into() } else { alternate_name(chr, idx).into() }
+}
+
+fn alternate_name(chr: char, idx: usize) -> String {
+    format!("{}.alt{idx}", glyph_name(chr))
+}
+
+/// Resolve each glyph and the sequence of glyphs it's replaced with,
+/// rejecting glyphs replaced more than once or with nothing.
+fn compile_sequences(
+    tag: &[u8; 4],
+    sequences: &[(GlyphRef, Vec<GlyphRef>)],
+    glyph_map: &GlyphMap,
+) -> Result<Vec<(GlyphId, Vec<GlyphId>)>, Error> {
+    let mut seen = HashSet::new();
+    sequences.iter().map(|(from, to)| {
+        let glyph = glyph_map.resolve(from)?;
+        if !seen.insert(glyph) {
+            let tag = String::from_utf8_lossy(tag).into_owned();
+            return Err(Error::DuplicateSubstitution(tag, from.clone()));
+        }
+        if to.is_empty() {
+            return Err(Error::EmptySequence(from.clone()));
+        }
+        let to = to.iter().map(|glyph| glyph_map.resolve(glyph)).collect::<Result<_, _>>()?;
+        Ok((glyph, to))
+    }).collect()
+}
+
+/// Resolve each ligature rule to glyphs, rejecting rules which
+/// repeat or contradict an earlier rule.
+fn compile_ligatures(
+    rules: &[(Vec<GlyphRef>, GlyphRef)],
+    glyph_map: &GlyphMap,
+) -> Result<Vec<Ligature>, Error> {
+    let mut replacements: HashMap<Vec<GlyphId>, GlyphId> = HashMap::new();
+    let mut ligatures = Vec::new();
+    for (components, replacement) in rules {
+        if components.is_empty() {
+            return Err(Error::EmptyLigature(replacement.clone()));
+        }
+        let pattern: Vec<GlyphId> = components.iter()
+            .map(|glyph| glyph_map.resolve(glyph))
+            .collect::<Result<_, _>>()?;
+        let replacement = glyph_map.resolve(replacement)?;
+        match replacements.insert(pattern.clone(), replacement) {
+            Some(previous) if previous == replacement =>
+                return Err(Error::DuplicateLigature(components.clone())),
+            Some(_) => return Err(Error::ConflictingLigature(components.clone())),
+            None => ligatures.push((pattern, replacement)),
+        }
+    }
+    Ok(ligatures)
+}
+
+/// Convert an OpenType script, language, or feature tag to its four
+/// bytes, padding it with spaces.
+pub(crate) fn to_tag(tag: &str) -> Result<[u8; 4], Error> {
+    let valid = (1..=4).contains(&tag.len()) && tag.bytes().all(|byte| (0x20..=0x7E).contains(&byte));
+    if !valid {
+        return Err(Error::InvalidTag(tag.to_string()));
+    }
+    let mut bytes = [b' '; 4];
+    bytes[..tag.len()].copy_from_slice(tag.as_bytes());
+    Ok(bytes)
+}
+
+/// Resolve each of a feature's substitutions to glyphs, rejecting
+/// glyphs substituted more than once.
+fn compile_substitutions(
+    tag: &[u8; 4],
+    substitutions: &[Substitution],
+    glyph_map: &GlyphMap,
+) -> Result<Vec<(GlyphId, GlyphId)>, Error> {
+    let mut seen = HashSet::new();
+    substitutions.iter().map(|(from, to)| {
+        let glyph = glyph_map.resolve(from)?;
+        if !seen.insert(glyph) {
+            let tag = String::from_utf8_lossy(tag).into_owned();
+            return Err(Error::DuplicateSubstitution(tag, from.clone()));
+        }
+        Ok((glyph, glyph_map.resolve(to)?))
+    }).collect()
+}
+
+/// Add a lookup which applies the first matching of the contextual
+/// `rules`, along with the lookups nested within it. Rules which make
+/// the same (sorted) substitutions as a lookup in `nested` use it,
+/// rather than a new one.
+fn compile_contextual(
+    tag: &[u8; 4],
+    rules: &[ContextualRule],
+    glyph_map: &GlyphMap,
+    gsub: &mut GSubBuilder,
+    nested: &mut HashMap<Vec<(GlyphId, GlyphId)>, LookupId>,
+) -> Result<LookupId, Error> {
+    let resolve_class = |class: &Vec<GlyphRef>| {
+        class.iter().map(|glyph| glyph_map.resolve(glyph)).collect::<Result<Vec<_>, _>>()
+    };
+    let mut subtables = Vec::new();
+    for rule in rules {
+        if rule.input.is_empty() {
+            return Err(Error::EmptyContextualInput);
+        }
+        let mut lookups = Vec::new();
+        for (idx, position) in rule.input.iter().enumerate() {
+            if position.substitutions.is_empty() { continue; }
+            let mut subs = compile_substitutions(tag, &position.substitutions, glyph_map)?;
+            subs.sort_unstable();
+            let lookup = *nested.entry(subs)
+                .or_insert_with_key(|subs| gsub.lookup(vec![SubstSubtable::single(subs.clone())]));
+            lookups.push((idx as u16, lookup));
+        }
+        subtables.push(SubstSubtable::ChainContextSubst(ChainRule {
+            backtrack: rule.backtrack.iter().map(resolve_class).collect::<Result<_, _>>()?,
+            input: rule.input.iter().map(|position| resolve_class(&position.class)).collect::<Result<_, _>>()?,
+            lookahead: rule.lookahead.iter().map(resolve_class).collect::<Result<_, _>>()?,
+            lookups,
+        }));
+    }
+    Ok(gsub.lookup(subtables))
+}
+
+/// Which side of its base a combining mark attaches to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MarkPosition {
+    Above,
+    Below,
+}
+
+impl MarkPosition {
+    fn of(mark: char) -> Self {
+        if unicode::is_below(mark) { MarkPosition::Below } else { MarkPosition::Above }
+    }
+
+    /// The mark class of marks in this position.
+    fn class(self) -> u16 {
+        match self {
+            MarkPosition::Above => 0,
+            MarkPosition::Below => 1,
+        }
+    }
+}
+
+/// The shape a letter of a joining script (eg Arabic) takes, depending
+/// on which of its neighbours it connects to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum JoiningForm {
+    /// Connected to neither neighbour.
+    Isolated,
+    /// Connected only to the following letter.
+    Initial,
+    /// Connected to both neighbours.
+    Medial,
+    /// Connected only to the preceding letter.
+    Final,
+}
+
+impl JoiningForm {
+    const ALL: [JoiningForm; 4] = [
+        JoiningForm::Isolated, JoiningForm::Final, JoiningForm::Medial, JoiningForm::Initial,
+    ];
+
+    /// The feature which swaps in this form, which also suffixes the
+    /// form's glyph name.
+    fn tag(self) -> &'static [u8; 4] {
+        match self {
+            JoiningForm::Isolated => b"isol",
+            JoiningForm::Initial => b"init",
+            JoiningForm::Medial => b"medi",
+            JoiningForm::Final => b"fina",
+        }
+    }
+
+    /// Whether this form connects to the preceding letter, and to the
+    /// following letter.
+    fn connections(self) -> (bool, bool) {
+        match self {
+            JoiningForm::Isolated => (false, false),
+            JoiningForm::Initial => (false, true),
+            JoiningForm::Medial => (true, true),
+            JoiningForm::Final => (true, false),
+        }
+    }
+}
+
+/// The name of the glyph for `chr` in its joining `form`, eg "uni0628.init".
+fn joining_form_name(chr: char, form: JoiningForm) -> String {
+    format!("{}.{}", glyph_name(chr), String::from_utf8_lossy(form.tag()))
+}
+
+/// The name of the flipped glyph for `chr` in right-to-left text,
+/// eg "parenleft.rtlm".
+fn mirrored_name(chr: char) -> String {
+    format!("{}.rtlm", glyph_name(chr))
+}
+
+/// The lowercase form of the letter `chr`, or `chr` itself if it has
+/// no single-char lowercase form.
+fn to_lowercase(chr: char) -> char {
+    let mut lowercase = chr.to_lowercase();
+    match (lowercase.next(), lowercase.next()) {
+        (Some(lower), None) => lower,
+        _ => chr,
+    }
+}
+
+/// The uppercase form of the letter `chr`, or `chr` itself if it has
+/// no single-char uppercase form.
+fn to_uppercase(chr: char) -> char {
+    let mut uppercase = chr.to_uppercase();
+    match (uppercase.next(), uppercase.next()) {
+        (Some(upper), None) => upper,
+        _ => chr,
+    }
+}
+
+/// The most digits in a numerator the fraction feature (`frac`) recognizes.
+const MAX_NUMERATOR_DIGITS: usize = 10;
+
+/// The name of the numerator form of `digit`, eg "one.numr".
+fn numerator_name(digit: char) -> String {
+    format!("{}.numr", glyph_name(digit))
+}
+
+/// The name of the denominator form of `digit`, eg "one.dnom".
+fn denominator_name(digit: char) -> String {
+    format!("{}.dnom", glyph_name(digit))
+}
+
+/// The name of the small cap glyph for the lowercase letter `chr`, eg "a.sc".
+fn small_cap_name(chr: char) -> String {
+    format!("{}.sc", glyph_name(chr))
+}
+
+/// Settings for kerning pairs automatically, by comparing the ink at
+/// the facing edges of their sprites, row by row.
+///
+/// Each pair is adjusted so its closest ink is `gap` blank pixels
+/// apart. Ink in neighbouring rows counts, so pixels can't touch
+/// diagonally either. Pairs with no ink facing each other aren't kerned.
+#[derive(Clone, Debug)]
+pub struct AutoKerning {
+    gap: i16,
+    threshold: i16,
+    classes: Vec<Vec<char>>,
+}
+
+impl AutoKerning {
+    /// Kern pairs to leave `gap` blank pixels between their closest ink.
+    pub fn new(gap: i16) -> Self {
+        AutoKerning { gap, threshold: 1, classes: Vec::new() }
+    }
+
+    /// Only kern pairs which would be adjusted by at least `pixels`,
+    /// tighter or looser. Defaults to 1.
+    pub fn threshold(mut self, pixels: i16) -> Self {
+        self.threshold = pixels;
+        self
+    }
+
+    /// Kern pairs of chars within `chars`, eg all the Latin letters.
+    /// Chars are only kerned against chars in the same class. With no
+    /// classes, every drawn char is kerned against every other.
+    pub fn class<I: IntoIterator<Item=char>>(mut self, chars: I) -> Self {
+        self.classes.push(chars.into_iter().collect());
+        self
+    }
+}
+
+/// Settings for spacing glyphs by their ink, rather than by the blank
+/// columns left in their sprites.
+///
+/// Each drawn glyph is shifted so its ink starts `left` pixels past
+/// its origin, and advances `right` pixels past the end of its ink,
+/// plus the tracking. Blank glyphs, like space, keep the full sprite
+/// width, plus the tracking.
+#[derive(Clone, Debug)]
+pub struct Spacing {
+    left: i16,
+    right: i16,
+    tracking: i16,
+    side_bearings: HashMap<GlyphRef, (i16, i16)>,
+}
+
+impl Spacing {
+    /// Pad each glyph's ink with `left` and `right` blank pixels.
+    pub fn new(left: i16, right: i16) -> Self {
+        Spacing { left, right, tracking: 0, side_bearings: HashMap::new() }
+    }
+
+    /// Add `pixels` to every glyph's advance: positive to loosen,
+    /// negative to tighten.
+    pub fn tracking(mut self, pixels: i16) -> Self {
+        self.tracking = pixels;
+        self
+    }
+
+    /// Pad the ink of `glyph` with `left` and `right` blank pixels,
+    /// rather than the defaults.
+    pub fn side_bearings(mut self, glyph: impl Into<GlyphRef>, left: i16, right: i16) -> Self {
+        self.side_bearings.insert(glyph.into(), (left, right));
+        self
+    }
+}
+
+/// How wide the digits are: each as wide as its ink needs, or all the
+/// same width, so they line up in columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Figures {
+    Proportional,
+    Tabular,
+}
+
+impl Figures {
+    fn other(self) -> Self {
+        match self {
+            Figures::Proportional => Figures::Tabular,
+            Figures::Tabular => Figures::Proportional,
+        }
+    }
+
+    /// The feature which swaps in these figures, which also suffixes
+    /// their glyph names.
+    fn tag(self) -> &'static [u8; 4] {
+        match self {
+            Figures::Proportional => b"pnum",
+            Figures::Tabular => b"tnum",
+        }
+    }
+}
+
+/// The name of the glyph for `digit` in the `figures` which aren't the
+/// default, eg "one.tnum".
+fn figure_name(digit: char, figures: Figures) -> String {
+    format!("{}.{}", glyph_name(digit), String::from_utf8_lossy(figures.tag()))
+}
+
+/// The fewest blank pixels between the ink of two sprites, given the
+/// `row_extents` of each, when `right` is drawn `advance` pixels after
+/// `left`. Returns `None` if no ink faces across the pair.
+fn ink_gap(left: &[Option<(i16, i16)>], right: &[Option<(i16, i16)>], advance: i16) -> Option<i16> {
+    let mut gap: Option<i16> = None;
+    for (y, extent) in left.iter().enumerate() {
+        let Some((_, left_max)) = extent else { continue };
+        for neighbour in y.saturating_sub(1)..=y + 1 {
+            if let Some(&Some((right_min, _))) = right.get(neighbour) {
+                let row_gap = advance + right_min - left_max;
+                gap = Some(gap.map_or(row_gap, |gap| gap.min(row_gap)));
+            }
+        }
+    }
+    gap
+}
+
+/// A glyph, and the glyph to replace it with.
+type Substitution = (GlyphRef, GlyphRef);
+
+/// The kinds of optional feature which swap glyphs for their alternates.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Variants {
+    /// `ss01` through `ss20`.
+    StylisticSet,
+    /// `cv01` through `cv99`.
+    CharacterVariant,
+}
+
+impl Variants {
+    fn tag(self, number: u8) -> Result<[u8; 4], Error> {
+        let (prefix, max) = match self {
+            Variants::StylisticSet => ("ss", 20),
+            Variants::CharacterVariant => ("cv", 99),
+        };
+        let tag = format!("{prefix}{number:02}");
+        match tag.as_bytes().try_into() {
+            Ok(bytes) if (1..=max).contains(&number) => Ok(bytes),
+            _ => Err(Error::UnsupportedFeature(tag)),
+        }
+    }
+}
+
+/// Incrementally describe a bitmap font, then `build` it.
+///
+/// All sprites in the font share the same `width` and `height`, and are
+/// expressed as bit-aligned bitmaps padded to byte boundaries.
+pub struct FontBuilder<'a> {
+    width: usize,
+    height: usize,
+    missing_glyph: Bitmap<'a>,
+    glyphs: BTreeMap<char, Bitmap<'a>>,
+    named_glyphs: Vec<(String, Bitmap<'a>)>,
+    ligature_rules: Vec<(Vec<GlyphRef>, GlyphRef)>,
+    required_ligature_rules: Vec<(Vec<GlyphRef>, GlyphRef)>,
+    /// Each char's sprite in each of its joining forms.
+    joining_forms: BTreeMap<(char, JoiningForm), Bitmap<'a>>,
+    mirrored_forms: bool,
+    calt_rules: Vec<ContextualRule>,
+    decompositions: Vec<(GlyphRef, Vec<GlyphRef>)>,
+    alternates: Vec<(GlyphRef, Vec<GlyphRef>)>,
+    /// The number of alternates each char cycles through.
+    cycled_alternates: BTreeMap<char, usize>,
+    /// Each script tag registered, and its language tags.
+    scripts: BTreeMap<String, BTreeSet<String>>,
+    /// Language-specific substitutions, by script and language tag.
+    localized_forms: BTreeMap<(String, String), Vec<Substitution>>,
+    /// Each feature's UI name, and its substitutions.
+    variants: BTreeMap<(Variants, u8), (String, Vec<Substitution>)>,
+    /// Each kerning pair and its adjustment in pixels, in the order given.
+    kerning: Vec<(GlyphRef, GlyphRef, i16)>,
+    /// Each pair of kerning classes and its adjustment in pixels.
+    kerning_classes: Vec<(Vec<GlyphRef>, Vec<GlyphRef>, i16)>,
+    auto_kerning: Option<AutoKerning>,
+    spacing: Option<Spacing>,
+    /// Each mark's sprite, and its anchor if given.
+    marks: BTreeMap<char, (Bitmap<'a>, Option<Anchor>)>,
+    base_anchors: HashMap<(char, MarkPosition), Anchor>,
+    aliases: BTreeMap<char, char>,
+    common_aliases: bool,
+    glyph_order: Vec<String>,
+    /// The width of the digits drawn with `glyph`, if they have alternates.
+    figures: Option<Figures>,
+    /// Each digit's sprite in the alternate figures, if not its default.
+    alternate_figures: BTreeMap<char, Bitmap<'a>>,
+    /// Each char's superscript and subscript forms, and their sprites.
+    superscripts: BTreeMap<char, (GlyphRef, Bitmap<'a>)>,
+    subscripts: BTreeMap<char, (GlyphRef, Bitmap<'a>)>,
+    /// Each digit with numerator and denominator forms.
+    fraction_digits: BTreeSet<char>,
+    /// Each letter's small cap sprite, by lowercase letter.
+    small_caps: BTreeMap<char, Bitmap<'a>>,
+    x_height: i16,
+    cap_height: i16,
+    small_cap_height: Option<i16>,
+    /// Feature files, compiled after the builder's own features.
+    feature_files: Vec<FeatureFile>,
+}
+
+/// A glyph to be built, in glyph order.
+struct GlyphEntry<'a> {
+    name: String,
+    chr: Option<char>,
+    source: GlyphSource<'a>,
+    /// How far to shift a sprite's outline right, to space it.
+    shift: i16,
+    advance: u16,
+}
+
+impl GlyphEntry<'_> {
+    fn glyph_ref(&self) -> GlyphRef {
+        match self.chr {
+            Some(chr) => chr.into(),
+            None => self.name.as_str().into(),
+        }
+    }
+}
+
+/// How to draw a glyph.
+enum GlyphSource<'a> {
+    Empty,
+    Sprite(Bitmap<'a>),
+    /// A sprite, flipped horizontally.
+    Mirrored(Bitmap<'a>),
+    /// Other encoded glyphs, and the offset to draw each at.
+    Composite(Vec<(char, i16, i16)>),
+}
+
+impl<'a> FontBuilder<'a> {
+    pub fn new(width: usize, height: usize, missing_glyph: Bitmap<'a>) -> Self {
+        FontBuilder {
+            width,
+            height,
+            missing_glyph,
+            glyphs: BTreeMap::new(),
+            named_glyphs: Vec::new(),
+            ligature_rules: Vec::new(),
+            required_ligature_rules: Vec::new(),
+            joining_forms: BTreeMap::new(),
+            mirrored_forms: false,
+            calt_rules: Vec::new(),
+            decompositions: Vec::new(),
+            alternates: Vec::new(),
+            cycled_alternates: BTreeMap::new(),
+            scripts: BTreeMap::new(),
+            localized_forms: BTreeMap::new(),
+            variants: BTreeMap::new(),
+            kerning: Vec::new(),
+            kerning_classes: Vec::new(),
+            auto_kerning: None,
+            spacing: None,
+            marks: BTreeMap::new(),
+            base_anchors: HashMap::new(),
+            aliases: BTreeMap::new(),
+            common_aliases: false,
+            glyph_order: Vec::new(),
+            figures: None,
+            alternate_figures: BTreeMap::new(),
+            superscripts: BTreeMap::new(),
+            subscripts: BTreeMap::new(),
+            fraction_digits: BTreeSet::new(),
+            small_caps: BTreeMap::new(),
+            x_height: 5,
+            cap_height: 7,
+            small_cap_height: None,
+            feature_files: Vec::new(),
+        }
+    }
+
+    /// Add a glyph for the character `chr`.
+    pub fn glyph(&mut self, chr: char, bitmap: Bitmap<'a>) -> &mut Self {
+        self.glyphs.insert(chr, bitmap);
+        self
+    }
+
+    pub fn glyphs<G>(&mut self, glyphs: G) -> &mut Self
+    where G: IntoIterator<Item=(char, Bitmap<'a>)> {
+        self.glyphs.extend(glyphs);
+        self
+    }
+
+    /// Add a glyph with no character of its own, eg "a.alt".
+    /// It can only be reached by substitutions which refer to it by name.
+    pub fn named_glyph(&mut self, name: impl Into<String>, bitmap: Bitmap<'a>) -> &mut Self {
+        self.named_glyphs.push((name.into(), bitmap));
+        self
+    }
+
+    /// Add a glyph which replaces the sequence of characters `seq`
+    /// when ligatures are enabled.
+    ///
+    /// The glyph is named after its components, eg "f_f_i".
+    pub fn ligature(&mut self, seq: &str, bitmap: Bitmap<'a>) -> &mut Self {
+        let components: Vec<_> = seq.chars().collect();
+        let name = components.iter()
+            .map(|&chr| glyph_name(chr))
+            .collect::<Vec<_>>()
+            .join("_");
+        self.named_glyph(name.clone(), bitmap);
+        self.ligature_rule(components, name)
+    }
+
+    pub fn ligatures<L>(&mut self, ligatures: L) -> &mut Self
+    where L: IntoIterator<Item=(&'a str, Bitmap<'a>)> {
+        for (seq, bitmap) in ligatures {
+            self.ligature(seq, bitmap);
+        }
+        self
+    }
+
+    /// Replace the sequence of `components` with the glyph `replacement`
+    /// when ligatures are enabled.
+    pub fn ligature_rule<I, G>(&mut self, components: I, replacement: impl Into<GlyphRef>) -> &mut Self
+    where
+        I: IntoIterator<Item=G>,
+        G: Into<GlyphRef>,
+    {
+        let components = components.into_iter().map(Into::into).collect();
+        self.ligature_rules.push((components, replacement.into()));
+        self
+    }
+
+    /// Replace the sequence of `components` with the glyph `replacement`,
+    /// even when ligatures are disabled (`rlig`). This is for ligatures
+    /// the script requires, eg Arabic lam-alef, whose components are
+    /// usually their joining forms (eg "uni0644.init", "uni0627.fina").
+    pub fn required_ligature<I, G>(&mut self, components: I, replacement: impl Into<GlyphRef>) -> &mut Self
+    where
+        I: IntoIterator<Item=G>,
+        G: Into<GlyphRef>,
+    {
+        let components = components.into_iter().map(Into::into).collect();
+        self.required_ligature_rules.push((components, replacement.into()));
+        self
+    }
+
+    /// Add the glyph for `chr` in its joining `form`, named eg
+    /// "uni0628.init", for joining scripts such as Arabic and Syriac.
+    /// Shapers swap it in (with `isol`, `init`, `medi` or `fina`) where
+    /// `chr` takes that form.
+    ///
+    /// The forms which connect to their neighbours are joined up by
+    /// cursive attachment (`curs`): right to left, the left edge of
+    /// each one's ink meets the right edge of the next one's, on the
+    /// baseline. So forms should be drawn with their connecting strokes
+    /// reaching the edges of their ink.
+    pub fn joining_form(&mut self, chr: char, form: JoiningForm, bitmap: Bitmap<'a>) -> &mut Self {
+        self.named_glyph(joining_form_name(chr, form), bitmap);
+        self.joining_forms.insert((chr, form), bitmap);
+        self
+    }
+
+    /// Add a horizontally flipped glyph for each char in the font which
+    /// is mirrored in right-to-left text (eg brackets and guillemets),
+    /// named eg "parenleft.rtlm". Shapers swap them in (with `rtlm`)
+    /// when they can't mirror a char by drawing its mirror image char
+    /// (eg ')' for '(') instead.
+    pub fn mirrored_forms(&mut self) -> &mut Self {
+        self.mirrored_forms = true;
+        self
+    }
+
+    /// Adjust the space between `left` and `right` by `pixels` when they
+    /// appear next to each other: negative to tighten, positive to loosen.
+    /// If a pair is kerned more than once, the last adjustment wins.
+    pub fn kern(&mut self, left: impl Into<GlyphRef>, right: impl Into<GlyphRef>, pixels: i16) -> &mut Self {
+        self.kerning.push((left.into(), right.into(), pixels));
+        self
+    }
+
+    pub fn kerning<I, A, B>(&mut self, pairs: I) -> &mut Self
+    where
+        I: IntoIterator<Item=(A, B, i16)>,
+        A: Into<GlyphRef>,
+        B: Into<GlyphRef>,
+    {
+        for (left, right, pixels) in pairs {
+            self.kern(left, right, pixels);
+        }
+        self
+    }
+
+    /// Adjust the space between any glyph in `left` and any glyph in
+    /// `right` by `pixels`, eg to kern every round letter against 'T'.
+    /// Kerning pairs given with `kern` take priority over classes.
+    ///
+    /// A glyph may be in many classes, but only one distinct class on
+    /// each side.
+    pub fn kern_classes<L, R, A, B>(&mut self, left: L, right: R, pixels: i16) -> &mut Self
+    where
+        L: IntoIterator<Item=A>,
+        R: IntoIterator<Item=B>,
+        A: Into<GlyphRef>,
+        B: Into<GlyphRef>,
+    {
+        let left = left.into_iter().map(Into::into).collect();
+        let right = right.into_iter().map(Into::into).collect();
+        self.kerning_classes.push((left, right, pixels));
+        self
+    }
+
+    /// Kern pairs of drawn chars automatically, from their sprites.
+    /// Pairs covered by `kern_classes` are left to the classes, and
+    /// pairs given to `kern` override the automatic adjustment.
+    pub fn auto_kern(&mut self, settings: AutoKerning) -> &mut Self {
+        self.auto_kerning = Some(settings);
+        self
+    }
+
+    /// Space glyphs by their ink, rather than giving each the full
+    /// sprite width.
+    pub fn spacing(&mut self, spacing: Spacing) -> &mut Self {
+        self.spacing = Some(spacing);
+        self
+    }
+
+    /// Add a combining mark (eg U+0301 COMBINING ACUTE ACCENT).
+    /// `anchor` is the point on the mark's sprite which should meet the
+    /// base glyph: typically just under the mark for marks above, and
+    /// just over it for marks below. If it's `None`, it's inferred from
+    /// the sprite: the bottom center of the ink for marks above, and the
+    /// top center for marks below.
+    ///
+    /// Marks have no advance width of their own. When a mark follows a
+    /// glyph, the mark (`mark`) and mark-to-mark (`mkmk`) positioning
+    /// features attach it to the glyph, or to the mark before it.
+    ///
+    /// Any precomposed letter which canonically decomposes to glyphs
+    /// and marks in this font (eg 'é' -> 'e' + U+0301) will be
+    /// synthesized as a compound glyph, unless it's drawn explicitly.
+    pub fn mark(&mut self, chr: char, bitmap: Bitmap<'a>, anchor: impl Into<Option<Anchor>>) -> &mut Self {
+        self.marks.insert(chr, (bitmap, anchor.into()));
+        self
+    }
+
+    /// Override the point at which marks attach to the glyph `chr`.
+    ///
+    /// By default marks above attach at the horizontal center of the
+    /// ink, at the cap height for uppercase letters, the small cap
+    /// height for small caps, and the x-height otherwise (or the top of
+    /// the ink, if that's higher). Marks below attach at the bottom of
+    /// the ink. Named glyphs are treated the same, as uppercase if their
+    /// name starts with an uppercase letter. Mirrored forms take their
+    /// char's anchors, mirrored, and further marks on a composed letter
+    /// stack past the marks it's made with.
+    pub fn base_anchor(&mut self, chr: char, position: MarkPosition, anchor: Anchor) -> &mut Self {
+        self.base_anchors.insert((chr, position), anchor);
+        self
+    }
+
+    /// Map the character `chr` to the same glyph as `target`, rather
+    /// than giving it a glyph of its own.
+    pub fn alias(&mut self, chr: char, target: char) -> &mut Self {
+        self.aliases.insert(chr, target);
+        self
+    }
+
+    /// Alias any characters which are conventionally drawn the same as
+    /// one in the font, unless they're drawn or aliased explicitly:
+    /// eg U+2010 HYPHEN to '-', Cyrillic 'а' to Latin 'a', and fullwidth
+    /// forms to ASCII.
+    pub fn common_aliases(&mut self) -> &mut Self {
+        self.common_aliases = true;
+        self
+    }
+
+    /// Apply the contextual substitution `rule` when contextual
+    /// alternates (`calt`) are enabled. At each glyph, the first
+    /// rule which matches is applied.
+    pub fn calt_rule(&mut self, rule: ContextualRule) -> &mut Self {
+        self.calt_rules.push(rule);
+        self
+    }
+
+    /// Replace `glyph` with the sequence of glyphs `components` when
+    /// glyph composition (`ccmp`) is enabled, eg to draw a precomposed
+    /// char as its base and mark.
+    pub fn decomposition<I, G>(&mut self, glyph: impl Into<GlyphRef>, components: I) -> &mut Self
+    where
+        I: IntoIterator<Item=G>,
+        G: Into<GlyphRef>,
+    {
+        let components = components.into_iter().map(Into::into).collect();
+        self.decompositions.push((glyph.into(), components));
+        self
+    }
+
+    /// Offer `alternates` for `glyph` as stylistic alternates (`salt`).
+    /// Like every alternate in the font, they're also offered by access
+    /// all alternates (`aalt`), which design apps use for glyph pickers.
+    pub fn alternates<I, G>(&mut self, glyph: impl Into<GlyphRef>, alternates: I) -> &mut Self
+    where
+        I: IntoIterator<Item=G>,
+        G: Into<GlyphRef>,
+    {
+        let alternates = alternates.into_iter().map(Into::into).collect();
+        self.alternates.push((glyph.into(), alternates));
+        self
+    }
+
+    /// Add `alternates` for the glyph of `chr`, named eg "a.alt1",
+    /// "a.alt2". When contextual alternates (`calt`) are enabled, runs
+    /// of chars with alternates rotate through them, so repeated letters
+    /// don't look stamped out.
+    ///
+    /// Each glyph takes the form after the preceding glyph's form:
+    /// following a char's default form is its first alternate, and
+    /// following a char's last alternate is its default form again.
+    pub fn cycle_alternates<I>(&mut self, chr: char, alternates: I) -> &mut Self
+    where I: IntoIterator<Item=Bitmap<'a>> {
+        for bitmap in alternates {
+            let count = self.cycled_alternates.entry(chr).or_default();
+            *count += 1;
+            let name = alternate_name(chr, *count);
+            self.named_glyph(name, bitmap);
+        }
+        self
+    }
+
+    /// Register the OpenType `script` tag (eg "latn", "cyrl", "grek"),
+    /// along with tags for `languages` written in it (eg "TRK", "BGR").
+    /// The font's features apply to text tagged with any of them,
+    /// as well as to text in other scripts.
+    pub fn script<I, S>(&mut self, script: &str, languages: I) -> &mut Self
+    where
+        I: IntoIterator<Item=S>,
+        S: Into<String>,
+    {
+        self.scripts.entry(script.to_string())
+            .or_default()
+            .extend(languages.into_iter().map(Into::into));
+        self
+    }
+
+    /// Swap each glyph in `substitutions` for its language-specific
+    /// form (`locl`) in text tagged with the `language` of `script`,
+    /// eg Bulgarian ("cyrl", "BGR") or Turkish ("latn", "TRK").
+    /// Registers the script and language as well.
+    pub fn localized_forms<I, A, B>(&mut self, script: &str, language: &str, substitutions: I) -> &mut Self
+    where
+        I: IntoIterator<Item=(A, B)>,
+        A: Into<GlyphRef>,
+        B: Into<GlyphRef>,
+    {
+        self.script(script, [language]);
+        self.localized_forms.entry((script.to_string(), language.to_string()))
+            .or_default()
+            .extend(substitutions.into_iter().map(|(from, to)| (from.into(), to.into())));
+        self
+    }
+
+    /// Swap each glyph in `substitutions` for its alternate when
+    /// stylistic set `number` (`ss01` through `ss20`) is enabled.
+    /// Font UIs label the set `name`, eg "Open 4".
+    ///
+    /// Adding to the same set again extends and renames it.
+    pub fn stylistic_set<I, A, B>(&mut self, number: u8, name: impl Into<String>, substitutions: I) -> &mut Self
+    where
+        I: IntoIterator<Item=(A, B)>,
+        A: Into<GlyphRef>,
+        B: Into<GlyphRef>,
+    {
+        self.variants(Variants::StylisticSet, number, name.into(), substitutions)
+    }
+
+    /// Swap each glyph in `substitutions` for its alternate when
+    /// character variant `number` (`cv01` through `cv99`) is enabled.
+    /// Font UIs label the variant `name`, eg "Slashed zero".
+    ///
+    /// Adding to the same variant again extends and renames it.
+    pub fn character_variant<I, A, B>(&mut self, number: u8, name: impl Into<String>, substitutions: I) -> &mut Self
+    where
+        I: IntoIterator<Item=(A, B)>,
+        A: Into<GlyphRef>,
+        B: Into<GlyphRef>,
+    {
+        self.variants(Variants::CharacterVariant, number, name.into(), substitutions)
+    }
+
+    fn variants<I, A, B>(&mut self, kind: Variants, number: u8, name: String, substitutions: I) -> &mut Self
+    where
+        I: IntoIterator<Item=(A, B)>,
+        A: Into<GlyphRef>,
+        B: Into<GlyphRef>,
+    {
+        let (ui_name, subs) = self.variants.entry((kind, number)).or_default();
+        *ui_name = name;
+        subs.extend(substitutions.into_iter().map(|(from, to)| (from.into(), to.into())));
+        self
+    }
+
+    /// Pin the glyph IDs of the named glyphs to their position in `names`.
+    /// Glyphs not named here keep their default relative order, after
+    /// all the pinned glyphs.
+    ///
+    /// Since `.notdef` must be glyph 0, `names` must begin with it.
+    /// `Font::glyph_order` provides an existing font's order to pin.
+    pub fn glyph_order<I, S>(&mut self, names: I) -> &mut Self
+    where
+        I: IntoIterator<Item=S>,
+        S: Into<String>,
+    {
+        self.glyph_order = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Pin the glyph order from a file of glyph names, one per line.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn glyph_order_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self, Error> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| Error::UnreadableFile(path.to_path_buf(), err.to_string()))?;
+        let names = text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        Ok(self.glyph_order(names))
+    }
+
+    /// The height in pixels of the font's flat lowercase letters, eg 'x'.
+    pub fn x_height(&mut self, x_height: i16) -> &mut Self {
+        self.x_height = x_height;
+        self
+    }
+
+    /// The height in pixels of the font's flat uppercase letters, eg 'H'.
+    pub fn cap_height(&mut self, cap_height: i16) -> &mut Self {
+        self.cap_height = cap_height;
+        self
+    }
+
+    /// Give the digits 0-9 `default` widths, and add alternates with the
+    /// other widths, named eg "one.tnum" or "one.pnum". Tabular figures
+    /// (`tnum`) and proportional figures (`pnum`) swap between them.
+    ///
+    /// Tabular digits are centered in the width of the widest digit.
+    /// Proportional digits are spaced like the font's other glyphs, or
+    /// with a blank pixel either side if the font isn't spaced.
+    /// Alternates are drawn like the default digits, unless drawn
+    /// separately with `alternate_figure`.
+    pub fn figures(&mut self, default: Figures) -> &mut Self {
+        self.figures = Some(default);
+        self
+    }
+
+    /// Draw the alternate to the default figures for `digit` as `bitmap`.
+    pub fn alternate_figure(&mut self, digit: char, bitmap: Bitmap<'a>) -> &mut Self {
+        self.alternate_figures.insert(digit, bitmap);
+        self
+    }
+
+    /// Add the superscript form of `chr`, which replaces it when
+    /// superscripts (`sups`) are enabled. Letters after digits take
+    /// their superscript forms when ordinals (`ordn`) are enabled, eg
+    /// "1st", "2nd".
+    ///
+    /// If Unicode has a superscript char for `chr` (eg '²' for '2'), the
+    /// form is that char's glyph, unless it's drawn separately.
+    /// Otherwise it's named eg "a.sups".
+    ///
+    /// The font's superscript metrics, which apps use to synthesize
+    /// superscripts, are measured from these sprites.
+    pub fn superscript(&mut self, chr: char, bitmap: Bitmap<'a>) -> &mut Self {
+        let form = self.script_form(chr, unicode::superscript(chr), "sups", bitmap);
+        self.superscripts.insert(chr, (form, bitmap));
+        self
+    }
+
+    /// Add the subscript form of `chr`, which replaces it when
+    /// subscripts (`subs`) are enabled.
+    ///
+    /// If Unicode has a subscript char for `chr` (eg '₂' for '2'), the
+    /// form is that char's glyph, unless it's drawn separately.
+    /// Otherwise it's named eg "a.subs".
+    ///
+    /// The font's subscript metrics, which apps use to synthesize
+    /// subscripts, are measured from these sprites.
+    pub fn subscript(&mut self, chr: char, bitmap: Bitmap<'a>) -> &mut Self {
+        let form = self.script_form(chr, unicode::subscript(chr), "subs", bitmap);
+        self.subscripts.insert(chr, (form, bitmap));
+        self
+    }
+
+    /// Add the glyph for a superscript or subscript form of `chr`: the
+    /// `encoded` char if there is one, otherwise named with `suffix`.
+    fn script_form(&mut self, chr: char, encoded: Option<char>, suffix: &str, bitmap: Bitmap<'a>) -> GlyphRef {
+        match encoded {
+            Some(encoded) => {
+                self.glyphs.entry(encoded).or_insert(bitmap);
+                encoded.into()
+            }
+            None => {
+                let name = format!("{}.{suffix}", glyph_name(chr));
+                self.named_glyph(name.clone(), bitmap);
+                name.into()
+            }
+        }
+    }
+
+    /// Add small numerator and denominator forms of each digit in
+    /// `digits`, eg "one.numr" and "one.dnom", along with `slash` as
+    /// the glyph for U+2044 FRACTION SLASH.
+    ///
+    /// The numerator (`numr`) and denominator (`dnom`) features swap in
+    /// the forms of every digit. The fraction feature (`frac`) draws
+    /// runs like "1/2" as fractions: a slash between digits becomes the
+    /// fraction slash, the digits before it numerators, and the digits
+    /// after it denominators.
+    pub fn fractions<I>(&mut self, digits: I, slash: Bitmap<'a>) -> &mut Self
+    where I: IntoIterator<Item=(char, Bitmap<'a>, Bitmap<'a>)> {
+        for (digit, numerator, denominator) in digits {
+            self.named_glyph(numerator_name(digit), numerator);
+            self.named_glyph(denominator_name(digit), denominator);
+            self.fraction_digits.insert(digit);
+        }
+        self.glyph('\u{2044}', slash)
+    }
+
+    /// The height in pixels of the font's flat small caps, eg "h.sc",
+    /// where marks above attach to them. Defaults to the x-height.
+    /// It only places mark anchors: the small caps are drawn as given.
+    pub fn small_cap_height(&mut self, small_cap_height: i16) -> &mut Self {
+        self.small_cap_height = Some(small_cap_height);
+        self
+    }
+
+    /// Add the small cap glyph for the letter `chr`, named after its
+    /// lowercase form, eg "a.sc". It replaces the lowercase letter
+    /// when small caps (`smcp`) are enabled, and the uppercase letter
+    /// when capitals to small caps (`c2sc`) are enabled.
+    pub fn small_cap(&mut self, chr: char, bitmap: Bitmap<'a>) -> &mut Self {
+        let lowercase = to_lowercase(chr);
+        self.named_glyph(small_cap_name(lowercase), bitmap);
+        self.small_caps.insert(lowercase, bitmap);
+        self
+    }
+
+    pub fn small_caps<G>(&mut self, small_caps: G) -> &mut Self
+    where G: IntoIterator<Item=(char, Bitmap<'a>)> {
+        for (chr, bitmap) in small_caps {
+            self.small_cap(chr, bitmap);
+        }
+        self
+    }
+
+    /// Add the lookups and features of a feature file, written in a
+    /// subset of the Adobe feature file syntax: glyph classes, `sub`
+    /// and `pos` rules, `lookup` and `feature` blocks, and `script` and
+    /// `language` statements. They apply after the builder's own.
+    ///
+    /// Syntax errors are returned here, and errors resolving glyph
+    /// names are returned by `build`, both with the line and column
+    /// they occur at.
+    pub fn features(&mut self, source: &str) -> Result<&mut Self, Error> {
+        self.feature_files.push(FeatureFile::parse(source)?);
+        Ok(self)
+    }
+
+    pub fn build(&self) -> Result<Font, Error> {
+        // Every encoded glyph, keyed (and so ordered) by char.
+        let mut sources: BTreeMap<char, GlyphSource> = BTreeMap::new();
+        for (&chr, &bitmap) in &self.glyphs {
+            sources.insert(chr, GlyphSource::Sprite(bitmap));
+        }
+        for (&chr, &(bitmap, _)) in &self.marks {
+            sources.insert(chr, GlyphSource::Sprite(bitmap));
+        }
+        for chr in unicode::precomposed_chars() {
+            if sources.contains_key(&chr) { continue; }
+            if let Some(composition) = self.compose(chr) {
+                sources.insert(chr, GlyphSource::Composite(composition.placements));
+            }
+        }
+
+        for (name, _) in &self.named_glyphs {
+            if !is_valid_glyph_name(name) {
+                return Err(Error::InvalidGlyphName(name.clone()));
+            }
+        }
+        let advance = self.width as u16;
+        let entry = |name: &str, chr, source| GlyphEntry { name: name.to_string(), chr, source, shift: 0, advance };
+        let entries = [
+            entry(".notdef", None, GlyphSource::Sprite(self.missing_glyph)),
+            GlyphEntry { advance: 0, ..entry(".null", None, GlyphSource::Empty) },
+            entry("nonmarkingreturn", None, GlyphSource::Empty),
+        ].into_iter()
+            .chain(sources.into_iter().map(|(chr, source)| {
+                let (shift, advance) = match &source {
+                    _ if self.marks.contains_key(&chr) => (0, 0),
+                    GlyphSource::Sprite(bitmap) => self.metrics(&chr.into(), bitmap),
+                    // Composites are spaced like their base, which
+                    // their components are already placed relative to.
+                    GlyphSource::Composite(placements) => {
+                        let base = placements[0].0;
+                        (0, self.metrics(&base.into(), self.glyphs[&base]).1)
+                    }
+                    GlyphSource::Empty | GlyphSource::Mirrored(_) => (0, advance),
+                };
+                GlyphEntry { shift, advance, ..entry(&glyph_name(chr), Some(chr), source) }
+            }))
+            .chain(self.named_glyphs.iter().map(|&(ref name, bitmap)| {
+                let (shift, advance) = self.metrics(&name.as_str().into(), bitmap);
+                GlyphEntry { shift, advance, ..entry(name, None, GlyphSource::Sprite(bitmap)) }
+            }))
+            .chain(self.figure_alternates().map(|(name, bitmap)| {
+                let (shift, advance) = self.metrics(&name.as_str().into(), bitmap);
+                GlyphEntry { shift, advance, ..entry(&name, None, GlyphSource::Sprite(bitmap)) }
+            }))
+            .chain(self.mirrored_chars().map(|(chr, bitmap)| {
+                let name = mirrored_name(chr);
+                let (shift, advance) = self.sprite_metrics(&name.as_str().into(), &self.sprite(bitmap).mirrored());
+                GlyphEntry { shift, advance, ..entry(&name, None, GlyphSource::Mirrored(bitmap)) }
+            }))
+            .collect();
+        let entries = self.apply_glyph_order(entries)?;
+
+        let glyph_ids: HashMap<char, GlyphId> = entries.iter().zip((0..).map(GlyphId))
+            .filter_map(|(entry, glyph)| Some((entry.chr?, glyph)))
+            .collect();
+        let names: HashMap<String, GlyphId> = entries.iter().zip((0..).map(GlyphId))
+            .map(|(entry, glyph)| (entry.name.clone(), glyph))
+            .collect();
+        let glyph_map = GlyphMap {
+            chars: self.char_mapping(&glyph_ids, &names)?,
+            names,
+        };
+
+        // Composites are bounded by their components, so draw the
+        // simple glyphs first.
+        let simple_glyphs: Vec<Option<Glyph>> = entries.iter().map(|entry| match &entry.source {
+            GlyphSource::Empty => Some(Glyph::empty()),
+            GlyphSource::Sprite(bitmap) | GlyphSource::Mirrored(bitmap) => {
+                let sprite = self.sprite(bitmap);
+                let sprite = match entry.source {
+                    GlyphSource::Mirrored(_) => sprite.mirrored(),
+                    _ => sprite,
+                };
+                let glyph = Glyph::from(sprite);
+                let spaced = self.spacing.is_some() || self.figure_style(&entry.glyph_ref()).is_some();
+                Some(if spaced { glyph.translate(entry.shift) } else { glyph })
+            }
+            GlyphSource::Composite(_) => None,
+        }).collect();
+        let bounds: Vec<Option<Rect>> = simple_glyphs.iter()
+            .map(|glyph| glyph.as_ref().and_then(Glyph::bounds))
+            .collect();
+        let glyphs: Vec<Glyph> = entries.iter().zip(simple_glyphs).map(|(entry, glyph)| {
+            let GlyphSource::Composite(placements) = &entry.source else {
+                return glyph.expect("simple glyphs should be drawn");
+            };
+            let rect = placements.iter()
+                .filter_map(|&(chr, dx, dy)| Some(bounds[glyph_ids[&chr].0 as usize]?.translate(dx, dy)))
+                .reduce(|a, b| a.union(&b))
+                .unwrap_or(Rect { x_min: 0, y_min: 0, x_max: 0, y_max: 0 });
+            let components = placements.iter()
+                .map(|&(chr, dx, dy)| Component { glyph: glyph_ids[&chr], dx, dy })
+                .collect();
+            Glyph::compound(components, rect)
+        }).collect();
+        let metrics: Vec<_> = entries.iter().zip(&glyphs)
+            .map(|(entry, glyph)| (entry.advance, glyph.x_min()))
+            .collect();
+        let glyph_bounds: Vec<Option<Rect>> = glyphs.iter().map(Glyph::bounds).collect();
+        let glyf = Glyf::from(glyphs);
+        let loca = glyf.generate_loca();
+        let maxp = glyf.generate_maxp();
+        let cmap = CMap::from_mapping(glyph_map.chars.iter().map(|(&chr, &glyph)| (chr, glyph)))?;
+        let mut head = Head::new();
+        head.index_to_loc_format = loca.needs_long() as i16;  // XXX: 😬
+        // Composites may stack marks past the cell, which renderers
+        // would clip if the font's bounds and ascent didn't cover them.
+        let font_bounds = glyph_bounds.iter().flatten().copied().reduce(|a, b| a.union(&b));
+        if let Some(rect) = font_bounds {
+            head.rect = rect;
+        }
+        let mut name = Name::new();
+        // TODO: I think some or all of these are required,
+        // but this makes me sad. Revisit.
+        for id in [
+            COPYRIGHT_NOTICE, FONT_FAMILY, UNIQUE_SUBFAMILY_ID, FULL_FONT_NAME,
+            NAME_TABLE_VERSION, POSTSCRIPT_NAME, DESCRIPTION,
+        ] {
+            name.push(id, "My Neat Font");
+        }
+        name.push(FONT_SUBFAMILY, "Regular");
+        let mut hhea = HHea::new();
+        hhea.advance_width_max = entries.iter().map(|entry| entry.advance).max().unwrap_or(0);
+        if let Some(rect) = font_bounds {
+            hhea.ascent = hhea.ascent.max(rect.y_max);
+            hhea.descent = hhea.descent.min(rect.y_min);
+        }
+        // Only glyphs with outlines have side bearings.
+        let outlined: Vec<(i16, i16, Rect)> = metrics.iter().zip(&glyph_bounds)
+            .filter_map(|(&(advance, lsb), rect)| Some((advance as i16, lsb, (*rect)?)))
+            .collect();
+        let extents = outlined.iter().map(|&(_, lsb, rect)| lsb + (rect.x_max - rect.x_min));
+        hhea.min_left_side_bearing = outlined.iter().map(|&(_, lsb, _)| lsb).min().unwrap_or(0);
+        hhea.min_right_side_bearing = outlined.iter().zip(extents.clone())
+            .map(|(&(advance, _, _), extent)| advance - extent)
+            .min()
+            .unwrap_or(0);
+        hhea.x_max_extent = extents.max().unwrap_or(0);
+        let hmtx = HMtx::from_metrics(metrics);
+        hhea.num_of_long_hor_metrics = hmtx.num_of_long_hor_metrics() as u16;
+
+        let advances: Vec<i64> = entries.iter()
+            .map(|entry| entry.advance as i64)
+            .filter(|&advance| advance != 0)
+            .collect();
+        let is_monospace = advances.windows(2).all(|pair| pair[0] == pair[1]);
+        let names: Vec<_> = entries.iter().map(|entry| &entry.name).collect();
+        let post = Post::from_names(&names, is_monospace);
+
+        let gdef = self.build_gdef(&entries, &glyph_map)?;
+        let gsub = self.build_gsub(&glyph_map, &mut name)?;
+        let kern_classes = self.compile_kerning_classes(&glyph_map)?;
+        let kern_pairs = self.compile_kerning(&glyph_map, kern_classes.as_ref())?;
+        let kern = build_kern(&kern_pairs, kern_classes.as_ref());
+        let gpos = self.build_gpos(&glyph_map, kern_pairs, kern_classes)?;
+
+        let mut os2 = Os2::default();
+        let Os2::Version5(os2_v5) = &mut os2;
+        if is_monospace {
+            // Latin text, monospaced.
+            os2_v5.panose = [2, 0, 0, 9, 0, 0, 0, 0, 0, 0];
+        }
+        if !advances.is_empty() {
+            let total: i64 = advances.iter().sum();
+            let count = advances.len() as i64;
+            os2_v5.x_avg_char_width = ((total + count / 2) / count) as i16;
+        }
+        let units_per_em = head.units_per_em as f64;
+        if let Some(metrics) = self.script_metrics(&self.superscripts) {
+            os2_v5.y_superscript_x_size = (metrics.x_scale * units_per_em).round() as i16;
+            os2_v5.y_superscript_y_size = (metrics.y_scale * units_per_em).round() as i16;
+            os2_v5.y_superscript_x_offset = 0;
+            os2_v5.y_superscript_y_offset = metrics.y_offset;
+        }
+        if let Some(metrics) = self.script_metrics(&self.subscripts) {
+            os2_v5.y_subscript_x_size = (metrics.x_scale * units_per_em).round() as i16;
+            os2_v5.y_subscript_y_size = (metrics.y_scale * units_per_em).round() as i16;
+            os2_v5.y_subscript_x_offset = 0;
+            // Subscript offsets are measured downward.
+            os2_v5.y_subscript_y_offset = -metrics.y_offset;
+        }
+        if let Some(rect) = font_bounds {
+            os2_v5.s_typo_ascender = os2_v5.s_typo_ascender.max(rect.y_max);
+            os2_v5.s_typo_descender = os2_v5.s_typo_descender.min(rect.y_min);
+            os2_v5.us_win_ascent = os2_v5.us_win_ascent.max(rect.y_max.max(0) as u16);
+            os2_v5.us_win_descent = os2_v5.us_win_descent.max((-rect.y_min).max(0) as u16);
+        }
+        os2_v5.sx_height = self.x_height;
+        os2_v5.s_cap_height = self.cap_height;
+
+        let font = Font {
+            cmap,
+            gdef,
+            glyf,
+            gpos,
+            gsub,
+            head,
+            hhea,
+            hmtx,
+            kern,
+            loca,
+            maxp,
+            name,
+            os2,
+            post,
+        };
+        Ok(font)
+    }
+
+    /// Classify each glyph for shapers, and place text cursor carets
+    /// evenly between the components of each ligature.
+    fn build_gdef(&self, entries: &[GlyphEntry], glyph_map: &GlyphMap) -> Result<GDef, Error> {
+        let mut component_counts: HashMap<GlyphId, usize> = HashMap::new();
+        let required = compile_ligatures(&self.required_ligature_rules, glyph_map)?;
+        let optional = compile_ligatures(&self.ligature_rules, glyph_map)?;
+        for (pattern, glyph) in required.into_iter().chain(optional) {
+            component_counts.entry(glyph).or_insert(pattern.len());
+        }
+        // Unencoded glyphs which only make up part of a decomposed char.
+        let components: HashSet<GlyphId> = compile_sequences(b"ccmp", &self.decompositions, glyph_map)?
+            .into_iter()
+            .flat_map(|(_, sequence)| sequence)
+            .collect();
+
+        let classes = entries.iter().zip((0..).map(GlyphId)).map(|(entry, glyph)| {
+            let class = if component_counts.contains_key(&glyph) {
+                GlyphClass::Ligature
+            } else if entry.chr.is_some_and(|chr| self.marks.contains_key(&chr)) {
+                GlyphClass::Mark
+            } else if entry.chr.is_none() && components.contains(&glyph) {
+                GlyphClass::Component
+            } else {
+                GlyphClass::Base
+            };
+            (glyph, class)
+        });
+        let carets = component_counts.iter().map(|(&glyph, &count)| {
+            let advance = entries[glyph.0 as usize].advance as usize;
+            let carets = (1..count).map(|idx| (advance * idx / count) as i16).collect();
+            (glyph, carets)
+        });
+        Ok(GDef::new(classes, carets))
+    }
+
+    /// Resolve each kerning pair to glyphs, after any automatic ones.
+    /// If a pair is kerned more than once, the last adjustment wins.
+    fn compile_kerning(
+        &self,
+        glyph_map: &GlyphMap,
+        kern_classes: Option<&PairClasses>,
+    ) -> Result<Vec<(GlyphId, GlyphId, i16)>, Error> {
+        let mut pairs = BTreeMap::new();
+        if let Some(settings) = &self.auto_kerning {
+            // Glyphs on either side of the kerning classes.
+            let (firsts, seconds): (HashSet<GlyphId>, HashSet<GlyphId>) = match kern_classes {
+                Some(classes) => (
+                    classes.first.iter().flatten().copied().collect(),
+                    classes.second.iter().flatten().copied().collect(),
+                ),
+                None => Default::default(),
+            };
+            for (left, right, pixels) in self.auto_kern_pairs(settings) {
+                let pair = (glyph_map.resolve(&left.into())?, glyph_map.resolve(&right.into())?);
+                if !(firsts.contains(&pair.0) && seconds.contains(&pair.1)) {
+                    pairs.insert(pair, pixels);
+                }
+            }
+        }
+        for (left, right, pixels) in &self.kerning {
+            pairs.insert((glyph_map.resolve(left)?, glyph_map.resolve(right)?), *pixels);
+        }
+        Ok(pairs.into_iter().map(|((left, right), pixels)| (left, right, pixels)).collect())
+    }
+
+    /// Measure the pairs of drawn chars `settings` considers, and the
+    /// adjustment each needs.
+    fn auto_kern_pairs(&self, settings: &AutoKerning) -> Vec<(char, char, i16)> {
+        let is_drawn = |chr: &char| self.glyphs.contains_key(chr) && !self.marks.contains_key(chr);
+        let classes: Vec<Vec<char>> = if settings.classes.is_empty() {
+            vec![self.glyphs.keys().copied().filter(is_drawn).collect()]
+        } else {
+            settings.classes.iter()
+                .map(|class| class.iter().copied().filter(is_drawn).collect())
+                .collect()
+        };
+        // Each char's row extents, shifted as it's spaced, and its advance.
+        let extents: HashMap<char, _> = classes.iter().flatten().map(|&chr| {
+            let bitmap = self.glyphs[&chr];
+            let (shift, advance) = self.metrics(&chr.into(), bitmap);
+            let rows: Vec<_> = self.sprite(bitmap).row_extents().into_iter()
+                .map(|row| row.map(|(x_min, x_max)| (x_min + shift, x_max + shift)))
+                .collect();
+            (chr, (rows, advance as i16))
+        }).collect();
+
+        let mut pairs = Vec::new();
+        for class in classes {
+            for &left in &class {
+                for &right in &class {
+                    let ((left_rows, advance), (right_rows, _)) = (&extents[&left], &extents[&right]);
+                    let Some(gap) = ink_gap(left_rows, right_rows, *advance) else { continue };
+                    let adjustment = settings.gap - gap;
+                    if adjustment != 0 && adjustment.abs() >= settings.threshold {
+                        pairs.push((left, right, adjustment));
+                    }
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Resolve each pair of kerning classes to glyphs, numbering the
+    /// distinct classes on each side.
+    fn compile_kerning_classes(&self, glyph_map: &GlyphMap) -> Result<Option<PairClasses>, Error> {
+        if self.kerning_classes.is_empty() { return Ok(None); }
+        let mut first = ClassNumbering::default();
+        let mut second = ClassNumbering::default();
+        let mut adjustments = Vec::new();
+        for (left, right, pixels) in &self.kerning_classes {
+            adjustments.push((first.number(left, glyph_map)?, second.number(right, glyph_map)?, *pixels));
+        }
+        let mut values = vec![vec![0; second.classes.len()]; first.classes.len()];
+        for (left, right, pixels) in adjustments {
+            values[left][right] = pixels;
+        }
+        Ok(Some(PairClasses { first: first.classes, second: second.classes, values }))
+    }
+
+    /// Build the positioning features.
+    fn build_gpos(
+        &self,
+        glyph_map: &GlyphMap,
+        kern_pairs: Vec<(GlyphId, GlyphId, i16)>,
+        kern_classes: Option<PairClasses>,
+    ) -> Result<Option<GPos>, Error> {
+        let mut gpos = GPosBuilder::default();
+        self.register_scripts(&mut gpos)?;
+
+        // Subtables are tried in order, so pairs override classes.
+        let mut kern = Vec::new();
+        if !kern_pairs.is_empty() {
+            kern.push(PosSubtable::pairs(kern_pairs));
+        }
+        kern.extend(kern_classes.map(PosSubtable::ClassPairPos));
+        if !kern.is_empty() {
+            let lookup = gpos.lookup(kern);
+            gpos.feature(*b"kern", None, &[lookup]);
+        }
+        let connections = self.compile_cursive_connections(glyph_map)?;
+        if !connections.is_empty() {
+            // Marks between letters mustn't break their connection.
+            let flags = LookupFlags::RIGHT_TO_LEFT | LookupFlags::IGNORE_MARKS;
+            let lookup = gpos.flagged_lookup(flags, vec![PosSubtable::cursive(connections)]);
+            gpos.feature(*b"curs", None, &[lookup]);
+        }
+        if let Some((mark, mkmk)) = self.compile_mark_attachments(glyph_map)? {
+            let lookup = gpos.lookup(vec![PosSubtable::MarkBasePos(mark)]);
+            gpos.feature(*b"mark", None, &[lookup]);
+            let lookup = gpos.lookup(vec![PosSubtable::MarkMarkPos(mkmk)]);
+            gpos.feature(*b"mkmk", None, &[lookup]);
+        }
+        for file in &self.feature_files {
+            file.compile_gpos(glyph_map, &mut gpos)?;
+        }
+        gpos.build()
+    }
+
+    /// Register each of the font's scripts and languages with `table`,
+    /// along with any right-to-left scripts it draws chars from, whose
+    /// shapers only apply features registered for their script.
+    fn register_scripts<S: LookupSubtable>(&self, table: &mut LayoutBuilder<S>) -> Result<(), Error> {
+        for (script, languages) in &self.scripts {
+            let languages: Vec<_> = languages.iter().map(|language| to_tag(language)).collect::<Result<_, _>>()?;
+            table.script(to_tag(script)?, languages);
+        }
+        let rtl_scripts: BTreeSet<_> = self.glyphs.keys().chain(self.marks.keys())
+            .filter_map(|&chr| unicode::rtl_script(chr))
+            .collect();
+        for script in rtl_scripts {
+            table.script(to_tag(script)?, []);
+        }
+        Ok(())
+    }
+
+    /// Build the substitution features, in the order they apply.
+    fn build_gsub(&self, glyph_map: &GlyphMap, name: &mut Name) -> Result<Option<GSub>, Error> {
+        let mut gsub = GSubBuilder::default();
+        self.register_scripts(&mut gsub)?;
+
+        // Every alternate is offered by `aalt`, which applies first,
+        // so resolve the features it aggregates ahead of time.
+        let salt = compile_sequences(b"salt", &self.alternates, glyph_map)?;
+        let variants = self.variants.iter().map(|(&(kind, number), (ui_name, subs))| {
+            let tag = kind.tag(number)?;
+            Ok((kind, tag, ui_name, compile_substitutions(&tag, subs, glyph_map)?))
+        }).collect::<Result<Vec<_>, Error>>()?;
+        let cycled = self.cycled_alternates.iter().map(|(&chr, &count)| {
+            let forms = (1..=count).map(|idx| glyph_map.resolve(&cycled_form(chr, idx)));
+            Ok((glyph_map.resolve(&chr.into())?, forms.collect::<Result<_, _>>()?))
+        }).collect::<Result<Vec<_>, Error>>()?;
+        let singles = variants.iter()
+            .flat_map(|(_, _, _, subs)| subs.iter().map(|&(from, to)| (from, vec![to])));
+        let mut all_alternates: BTreeMap<GlyphId, Vec<GlyphId>> = BTreeMap::new();
+        for (glyph, alternates) in salt.iter().cloned().chain(cycled).chain(singles) {
+            let all = all_alternates.entry(glyph).or_default();
+            for alternate in alternates {
+                if !all.contains(&alternate) { all.push(alternate); }
+            }
+        }
+        if !all_alternates.is_empty() {
+            let lookup = gsub.lookup(vec![SubstSubtable::alternates(all_alternates.into_iter().collect())]);
+            gsub.feature(*b"aalt", None, &[lookup]);
+        }
+
+        let decompositions = compile_sequences(b"ccmp", &self.decompositions, glyph_map)?;
+        if !decompositions.is_empty() {
+            let lookup = gsub.lookup(vec![SubstSubtable::multiple(decompositions)]);
+            gsub.feature(*b"ccmp", None, &[lookup]);
+        }
+        // Language-specific forms come early, so the other features
+        // see the localized glyphs.
+        for ((script, language), subs) in &self.localized_forms {
+            let subs = compile_substitutions(b"locl", subs, glyph_map)?;
+            let lookup = gsub.lookup(vec![SubstSubtable::single(subs)]);
+            gsub.language_feature(to_tag(script)?, to_tag(language)?, *b"locl", &[lookup]);
+        }
+        let mirrored: Vec<_> = self.mirrored_chars()
+            .map(|(chr, _)| Ok((glyph_map.resolve(&chr.into())?, glyph_map.resolve(&mirrored_name(chr).into())?)))
+            .collect::<Result<_, Error>>()?;
+        if !mirrored.is_empty() {
+            let lookup = gsub.lookup(vec![SubstSubtable::single(mirrored)]);
+            gsub.feature(*b"rtlm", None, &[lookup]);
+        }
+        for form in JoiningForm::ALL {
+            let subs: Vec<_> = self.joining_forms.keys()
+                .filter(|&&(_, other)| other == form)
+                .map(|&(chr, _)| Ok((glyph_map.resolve(&chr.into())?, glyph_map.resolve(&joining_form_name(chr, form).into())?)))
+                .collect::<Result<_, Error>>()?;
+            if subs.is_empty() { continue; }
+            let lookup = gsub.lookup(vec![SubstSubtable::single(subs)]);
+            gsub.feature(*form.tag(), None, &[lookup]);
+        }
+        // Required ligatures see the joining forms, and come before the
+        // optional ones.
+        let required_ligatures = compile_ligatures(&self.required_ligature_rules, glyph_map)?;
+        if !required_ligatures.is_empty() {
+            let lookup = gsub.lookup(vec![SubstSubtable::ligatures(required_ligatures)]);
+            gsub.feature(*b"rlig", None, &[lookup]);
+        }
+        if let Some(default) = self.figures {
+            let alternates = self.figure_digits()
+                .map(|digit| Ok((glyph_map.resolve(&digit.into())?, glyph_map.resolve(&figure_name(digit, default.other()).into())?)))
+                .collect::<Result<Vec<_>, Error>>()?;
+            if !alternates.is_empty() {
+                let lookup = gsub.lookup(vec![SubstSubtable::single(alternates.clone())]);
+                gsub.feature(*default.other().tag(), None, &[lookup]);
+                let defaults = alternates.into_iter().map(|(digit, alternate)| (alternate, digit)).collect();
+                let lookup = gsub.lookup(vec![SubstSubtable::single(defaults)]);
+                gsub.feature(*default.tag(), None, &[lookup]);
+            }
+        }
+        for (tag, forms) in [(b"sups", &self.superscripts), (b"subs", &self.subscripts)] {
+            let subs: Vec<Substitution> = forms.iter().map(|(&chr, (form, _))| (chr.into(), form.clone())).collect();
+            if subs.is_empty() { continue; }
+            let lookup = gsub.lookup(vec![SubstSubtable::single(compile_substitutions(tag, &subs, glyph_map)?)]);
+            gsub.feature(*tag, None, &[lookup]);
+        }
+        let ordinals: Vec<Substitution> = self.superscripts.iter()
+            .filter(|(chr, _)| chr.is_alphabetic())
+            .map(|(&chr, (form, _))| (chr.into(), form.clone()))
+            .collect();
+        if !ordinals.is_empty() {
+            // Each letter follows a digit, or a letter already raised.
+            let preceding = ('0'..='9').filter(|digit| self.glyphs.contains_key(digit))
+                .map(GlyphRef::from)
+                .chain(ordinals.iter().map(|(_, form)| form.clone()));
+            let rule = ContextualRule::new().backtrack(preceding).substitute(ordinals);
+            let lookup = compile_contextual(b"ordn", &[rule], glyph_map, &mut gsub, &mut HashMap::new())?;
+            gsub.feature(*b"ordn", None, &[lookup]);
+        }
+        if !self.fraction_digits.is_empty() {
+            self.build_fractions(glyph_map, &mut gsub)?;
+        }
+        // Small caps come before ligatures, which they shouldn't form.
+        let c2sc = self.compile_small_caps(glyph_map, |chr| Some(to_uppercase(chr)).filter(|&upper| upper != chr))?;
+        if !c2sc.is_empty() {
+            let lookup = gsub.lookup(vec![SubstSubtable::single(c2sc)]);
+            gsub.feature(*b"c2sc", None, &[lookup]);
+        }
+        let smcp = self.compile_small_caps(glyph_map, Some)?;
+        if !smcp.is_empty() {
+            let lookup = gsub.lookup(vec![SubstSubtable::single(smcp)]);
+            gsub.feature(*b"smcp", None, &[lookup]);
+        }
+        let ligatures = compile_ligatures(&self.ligature_rules, glyph_map)?;
+        if !ligatures.is_empty() {
+            let lookup = gsub.lookup(vec![SubstSubtable::ligatures(ligatures)]);
+            gsub.feature(*b"liga", None, &[lookup]);
+        }
+        let calt_rules: Vec<_> = self.calt_rules.iter().cloned()
+            .chain(self.cycling_rules())
+            .collect();
+        if !calt_rules.is_empty() {
+            let lookup = compile_contextual(b"calt", &calt_rules, glyph_map, &mut gsub, &mut HashMap::new())?;
+            gsub.feature(*b"calt", None, &[lookup]);
+        }
+        if !salt.is_empty() {
+            let lookup = gsub.lookup(vec![SubstSubtable::alternates(salt)]);
+            gsub.feature(*b"salt", None, &[lookup]);
+        }
+        for (ui_name_id, (kind, tag, ui_name, subs)) in (FIRST_FONT_SPECIFIC..).zip(variants) {
+            name.push(ui_name_id, ui_name);
+            let params = match kind {
+                Variants::StylisticSet => FeatureParams::StylisticSet { ui_name_id },
+                Variants::CharacterVariant => {
+                    let mut chars: Vec<char> = glyph_map.chars.iter()
+                        .filter(|&(_, glyph)| subs.iter().any(|(from, _)| from == glyph))
+                        .map(|(&chr, _)| chr)
+                        .collect();
+                    chars.sort_unstable();
+                    FeatureParams::CharacterVariant { ui_name_id, chars }
+                }
+            };
+            let lookup = gsub.lookup(vec![SubstSubtable::single(subs)]);
+            gsub.feature(tag, Some(params), &[lookup]);
+        }
+        for file in &self.feature_files {
+            file.compile_gsub(glyph_map, &mut gsub)?;
+        }
+        gsub.build()
+    }
+
+    /// Add the numerator (`numr`), denominator (`dnom`) and fraction
+    /// (`frac`) features.
+    fn build_fractions(&self, glyph_map: &GlyphMap, gsub: &mut GSubBuilder) -> Result<(), Error> {
+        let digits: Vec<GlyphRef> = self.fraction_digits.iter().map(|&digit| digit.into()).collect();
+        let numerators: Vec<Substitution> = self.fraction_digits.iter()
+            .map(|&digit| (digit.into(), numerator_name(digit).into()))
+            .collect();
+        let denominators: Vec<Substitution> = self.fraction_digits.iter()
+            .map(|&digit| (digit.into(), denominator_name(digit).into()))
+            .collect();
+        // The frac rules reuse these lookups for their digits.
+        let mut nested = HashMap::new();
+        for (tag, substitutions) in [(b"numr", &numerators), (b"dnom", &denominators)] {
+            let mut subs = compile_substitutions(tag, substitutions, glyph_map)?;
+            subs.sort_unstable();
+            let lookup = gsub.lookup(vec![SubstSubtable::single(subs.clone())]);
+            gsub.feature(*tag, None, &[lookup]);
+            nested.insert(subs, lookup);
+        }
+
+        // Each lookup sees the last one's substitutions: first the
+        // slash, then the digits before it, and then the digits after
+        // it, each of which follows the slash or another denominator.
+        let fraction_slash = GlyphRef::from('\u{2044}');
+        let mut lookups = Vec::new();
+        if glyph_map.get(&'/'.into()).is_some() {
+            let rule = ContextualRule::new()
+                .backtrack(digits.clone())
+                .substitute([('/', fraction_slash.clone())])
+                .lookahead(digits.clone());
+            lookups.push(compile_contextual(b"frac", &[rule], glyph_map, gsub, &mut nested)?);
+        }
+        let numerator_rules: Vec<_> = (0..MAX_NUMERATOR_DIGITS).map(|count| {
+            let rule = ContextualRule::new().substitute(numerators.clone());
+            (0..count).fold(rule, |rule, _| rule.lookahead(digits.clone()))
+                .lookahead([fraction_slash.clone()])
+        }).collect();
+        lookups.push(compile_contextual(b"frac", &numerator_rules, glyph_map, gsub, &mut nested)?);
+        let preceding = denominators.iter().map(|(_, denominator)| denominator.clone())
+            .chain([fraction_slash]);
+        let denominator_rule = ContextualRule::new()
+            .backtrack(preceding)
+            .substitute(denominators.clone());
+        lookups.push(compile_contextual(b"frac", &[denominator_rule], glyph_map, gsub, &mut nested)?);
+        gsub.feature(*b"frac", None, &lookups);
+        Ok(())
+    }
+
+    /// Resolve the substitution of each small cap for the form of its
+    /// letter `case` gives, skipping letters which aren't in the font.
+    fn compile_small_caps<F>(&self, glyph_map: &GlyphMap, case: F) -> Result<Vec<(GlyphId, GlyphId)>, Error>
+    where F: Fn(char) -> Option<char> {
+        let mut subs = BTreeMap::new();
+        for &lowercase in self.small_caps.keys() {
+            let Some(glyph) = case(lowercase).and_then(|chr| glyph_map.get(&chr.into())) else { continue };
+            subs.insert(glyph, glyph_map.resolve(&small_cap_name(lowercase).into())?);
+        }
+        Ok(subs.into_iter().collect())
+    }
+
+    /// The contextual rules which rotate chars through their cycled
+    /// alternates: one rule per form, which moves the glyph after that
+    /// form on to its next form.
+    fn cycling_rules(&self) -> impl Iterator<Item=ContextualRule> + '_ {
+        let form_count = self.cycled_alternates.values().max().map_or(0, |&count| count + 1);
+        (0..form_count).filter_map(move |idx| {
+            let substitutions: Vec<_> = self.cycled_alternates.iter()
+                .map(|(&chr, &count)| (chr, (idx + 1) % (count + 1)))
+                .filter(|&(_, next)| next != 0)
+                .map(|(chr, next)| (chr, cycled_form(chr, next)))
+                .collect();
+            if substitutions.is_empty() { return None; }
+            let preceding = self.cycled_alternates.iter()
+                .filter(|&(_, &count)| idx <= count)
+                .map(|(&chr, _)| cycled_form(chr, idx));
+            Some(ContextualRule::new()
+                .backtrack(preceding)
+                .substitute(substitutions))
+        })
+    }
+
+    /// Reorder `entries` according to the pinned glyph order.
+    fn apply_glyph_order<'b>(&self, entries: Vec<GlyphEntry<'b>>) -> Result<Vec<GlyphEntry<'b>>, Error> {
+        let mut unordered: HashMap<String, GlyphEntry<'b>> = HashMap::new();
+        let mut default_order = Vec::new();
+        for entry in entries {
+            default_order.push(entry.name.clone());
+            if let Some(entry) = unordered.insert(entry.name.clone(), entry) {
+                return Err(Error::DuplicateGlyphName(entry.name));
+            }
+        }
+        if matches!(self.glyph_order.first(), Some(first) if first != ".notdef") {
+            return Err(Error::MisplacedNotdef);
+        }
+
+        let mut ordered = Vec::new();
+        for name in &self.glyph_order {
+            match unordered.remove(name) {
+                Some(entry) => ordered.push(entry),
+                None if default_order.contains(name) => {
+                    return Err(Error::DuplicateGlyphName(name.clone()));
+                }
+                None => return Err(Error::UnknownGlyph(GlyphRef::Name(name.clone()))),
+            }
+        }
+        for name in default_order {
+            if let Some(entry) = unordered.remove(&name) {
+                ordered.push(entry);
+            }
+        }
+        Ok(ordered)
+    }
+
+    /// Map each char to its glyph, including aliases, along with the
+    /// conventional mappings for any control and space characters not
+    /// drawn explicitly.
+    fn char_mapping(
+        &self,
+        glyph_ids: &HashMap<char, GlyphId>,
+        names: &HashMap<String, GlyphId>,
+    ) -> Result<HashMap<char, GlyphId>, Error> {
+        let mut mapping = glyph_ids.clone();
+        for (&chr, &target) in &self.aliases {
+            if glyph_ids.contains_key(&chr) {
+                return Err(Error::DuplicateChar(chr));
+            }
+            let &glyph = glyph_ids.get(&target).ok_or(Error::UnknownChar(target))?;
+            mapping.insert(chr, glyph);
+        }
+
+        let conventional = [
+            ('\0', names.get(".null").copied()),
+            ('\r', names.get("nonmarkingreturn").copied()),
+            ('\u{00A0}', glyph_ids.get(&' ').copied()),  // no-break space
+        ];
+        let common = unicode::common_aliases()
+            .filter(|_| self.common_aliases)
+            .map(|(chr, target)| (chr, glyph_ids.get(&target).copied()));
+        for (chr, glyph) in conventional.into_iter().chain(common) {
+            if let Some(glyph) = glyph {
+                mapping.entry(chr).or_insert(glyph);
+            }
+        }
+        Ok(mapping)
+    }
+
+    /// Each drawn char which is mirrored in right-to-left text, if the
+    /// font has mirrored forms.
+    fn mirrored_chars(&self) -> impl Iterator<Item=(char, Bitmap<'a>)> + '_ {
+        self.glyphs.iter()
+            .filter(|&(&chr, _)| self.mirrored_forms && unicode::is_mirrored(chr))
+            .map(|(&chr, &bitmap)| (chr, bitmap))
+    }
+
+    fn sprite(&self, bitmap: Bitmap) -> Sprite {
+        Sprite { width: self.width, height: self.height, data: bitmap.into() }
+    }
+
+    /// Lay out the precomposed `chr` from its base and marks, if they're
+    /// all in the font.
+    fn compose(&self, chr: char) -> Option<Composition> {
+        let (base, marks) = unicode::decompose(chr)?;
+        if !self.glyphs.contains_key(&base) { return None; }
+        if !marks.iter().all(|mark| self.marks.contains_key(mark)) { return None; }
+
+        // Marks above replace the dots on 'i' and 'j', if we can.
+        let dotless = match base {
+            'i' => 'ı',
+            'j' => 'ȷ',
+            _ => base,
+        };
+        let has_above = marks.iter().any(|&mark| MarkPosition::of(mark) == MarkPosition::Above);
+        let base = if has_above && self.glyphs.contains_key(&dotless) { dotless } else { base };
+
+        let mut above = self.attachment(base, MarkPosition::Above);
+        let mut below = self.attachment(base, MarkPosition::Below);
+        let mut placements = vec![(base, 0, 0)];
+        for mark in marks {
+            let (bitmap, _) = self.marks[&mark];
+            let anchor = self.mark_anchor(mark);
+            let position = MarkPosition::of(mark);
+            let attach = match position {
+                MarkPosition::Above => &mut above,
+                MarkPosition::Below => &mut below,
+            };
+            let (dx, dy) = (attach.x - anchor.x, attach.y - anchor.y);
+            placements.push((mark, dx, dy));
+
+            // Stack any further marks on this side past this one.
+            if let Some(ink) = self.sprite(bitmap).ink_bounds() {
+                attach.y = dy + match position {
+                    MarkPosition::Above => ink.y_max,
+                    MarkPosition::Below => ink.y_min,
+                };
+            }
+        }
+        Some(Composition { placements, above, below })
+    }
+
+    /// The point at which marks in `position` attach to `base`, after
+    /// it's spaced.
+    fn attachment(&self, base: char, position: MarkPosition) -> Anchor {
+        let bitmap = self.glyphs[&base];
+        let anchor = self.unspaced_attachment(base, position);
+        let (shift, _) = self.metrics(&base.into(), bitmap);
+        Anchor { x: anchor.x + shift, ..anchor }
+    }
+
+    /// The point at which marks in `position` attach to the mirrored
+    /// form of `base`, after it's spaced.
+    fn mirrored_attachment(&self, base: char, position: MarkPosition) -> Anchor {
+        let anchor = self.unspaced_attachment(base, position);
+        let sprite = self.sprite(self.glyphs[&base]).mirrored();
+        let (shift, _) = self.sprite_metrics(&mirrored_name(base).into(), &sprite);
+        Anchor { x: self.width as i16 - anchor.x + shift, ..anchor }
+    }
+
+    /// The point at which marks in `position` attach to `base`, on its
+    /// sprite's pixel grid.
+    fn unspaced_attachment(&self, base: char, position: MarkPosition) -> Anchor {
+        self.base_anchors.get(&(base, position)).copied().unwrap_or_else(|| {
+            let height = if base.is_uppercase() { self.cap_height } else { self.x_height };
+            self.infer_attachment(self.glyphs[&base], height, position)
+        })
+    }
+
+    /// The point at which marks in `position` attach to the named glyph
+    /// `name`, after it's spaced.
+    fn named_attachment(&self, name: &str, bitmap: Bitmap, position: MarkPosition) -> Anchor {
+        let height = if self.small_caps.keys().any(|&chr| small_cap_name(chr) == name) {
+            self.small_cap_height.unwrap_or(self.x_height)
+        } else if name.starts_with(char::is_uppercase) {
+            self.cap_height
+        } else {
+            self.x_height
+        };
+        let anchor = self.infer_attachment(bitmap, height, position);
+        let (shift, _) = self.metrics(&name.into(), bitmap);
+        Anchor { x: anchor.x + shift, ..anchor }
+    }
+
+    /// How far to shift the outline of `glyph`, drawn as `bitmap`, to
+    /// space it, and its advance width.
+    fn metrics(&self, glyph: &GlyphRef, bitmap: Bitmap) -> (i16, u16) {
+        self.sprite_metrics(glyph, &self.sprite(bitmap))
+    }
+
+    /// How far to shift the outline of `glyph`, drawn as `sprite`, to
+    /// space it, and its advance width.
+    fn sprite_metrics(&self, glyph: &GlyphRef, sprite: &Sprite) -> (i16, u16) {
+        match (self.figure_style(glyph), &self.spacing) {
+            (Some(Figures::Tabular), _) => self.tabular_metrics(sprite),
+            (Some(Figures::Proportional), None) => self.spaced_metrics(glyph, sprite, &Spacing::new(1, 1)),
+            (_, Some(spacing)) => self.spaced_metrics(glyph, sprite, spacing),
+            (None, None) => (0, self.width as u16),
+        }
+    }
+
+    /// How far to shift the outline of `glyph`, drawn as `sprite`, to
+    /// space it with `spacing`, and its advance width.
+    fn spaced_metrics(&self, glyph: &GlyphRef, sprite: &Sprite, spacing: &Spacing) -> (i16, u16) {
+        let width = self.width as i16;
+        let Some(ink) = sprite.ink_bounds() else {
+            return (0, (width + spacing.tracking).max(0) as u16);
+        };
+        let (left, right) = spacing.side_bearings.get(glyph).copied()
+            .unwrap_or((spacing.left, spacing.right));
+        let advance = left + (ink.x_max - ink.x_min) + right + spacing.tracking;
+        (left - ink.x_min, advance.max(0) as u16)
+    }
+
+    /// How the superscript or subscript `forms` compare to the glyphs of
+    /// the chars they're forms of, on average. Returns `None` if no form
+    /// and its char both have ink.
+    fn script_metrics(&self, forms: &BTreeMap<char, (GlyphRef, Bitmap)>) -> Option<ScriptMetrics> {
+        let pairs: Vec<(Rect, Rect)> = forms.iter().filter_map(|(chr, &(_, bitmap))| {
+            let base = self.sprite(self.glyphs.get(chr)?).ink_bounds()?;
+            Some((base, self.sprite(bitmap).ink_bounds()?))
+        }).collect();
+        if pairs.is_empty() { return None; }
+        let sum = |measure: &dyn Fn(&(Rect, Rect)) -> i16| {
+            pairs.iter().map(|pair| measure(pair) as f64).sum::<f64>()
+        };
+        let x_scale = sum(&|(_, form)| form.x_max - form.x_min) / sum(&|(base, _)| base.x_max - base.x_min);
+        let y_scale = sum(&|(_, form)| form.y_max - form.y_min) / sum(&|(base, _)| base.y_max - base.y_min);
+        // How far the form's baseline is raised, once the base is scaled.
+        let y_offset = pairs.iter()
+            .map(|(base, form)| form.y_min as f64 - base.y_min as f64 * y_scale)
+            .sum::<f64>() / pairs.len() as f64;
+        Some(ScriptMetrics { x_scale, y_scale, y_offset: y_offset.round() as i16 })
+    }
+
+    /// How far to shift the outline of a tabular digit drawn as `sprite`
+    /// to center it, and its advance width.
+    fn tabular_metrics(&self, sprite: &Sprite) -> (i16, u16) {
+        let width = self.tabular_width();
+        let Some(ink) = sprite.ink_bounds() else { return (0, width as u16) };
+        ((width - (ink.x_max - ink.x_min)) / 2 - ink.x_min, width as u16)
+    }
+
+    /// The width of tabular digits: that of the widest spaced digit, or
+    /// the sprite width if the font isn't spaced.
+    fn tabular_width(&self) -> i16 {
+        let Some(spacing) = &self.spacing else { return self.width as i16 };
+        let defaults = self.figure_digits().map(|digit| (digit, self.glyphs[&digit]));
+        defaults.chain(self.alternate_figures.iter().map(|(&digit, &bitmap)| (digit, bitmap)))
+            .map(|(digit, bitmap)| self.spaced_metrics(&digit.into(), &self.sprite(bitmap), spacing).1 as i16)
+            .max()
+            .unwrap_or(self.width as i16)
+    }
+
+    /// The digits which have alternate figures.
+    fn figure_digits(&self) -> impl Iterator<Item=char> + '_ {
+        ('0'..='9').filter(|digit| self.figures.is_some() && self.glyphs.contains_key(digit))
+    }
+
+    /// The name and sprite of each digit's alternate figure.
+    fn figure_alternates(&self) -> impl Iterator<Item=(String, Bitmap<'a>)> + '_ {
+        let alternate = self.figures.map(Figures::other);
+        alternate.into_iter().flat_map(move |figures| self.figure_digits().map(move |digit| {
+            let bitmap = self.alternate_figures.get(&digit).unwrap_or(&self.glyphs[&digit]);
+            (figure_name(digit, figures), *bitmap)
+        }))
+    }
+
+    /// Which figures `glyph` is one of, if it's a digit with alternates.
+    fn figure_style(&self, glyph: &GlyphRef) -> Option<Figures> {
+        let default = self.figures?;
+        match glyph {
+            GlyphRef::Char(chr) => self.figure_digits().any(|digit| digit == *chr).then_some(default),
+            GlyphRef::Name(name) => self.figure_digits()
+                .any(|digit| *name == figure_name(digit, default.other()))
+                .then_some(default.other()),
+        }
+    }
+
+    /// The point at which marks in `position` attach to the sprite
+    /// `bitmap`: for marks above, at `height`, unless the ink is taller.
+    fn infer_attachment(&self, bitmap: Bitmap, height: i16, position: MarkPosition) -> Anchor {
+        let ink = self.sprite(bitmap).ink_bounds();
+        let x = ink.map_or(self.width as i16 / 2, |ink| (ink.x_min + ink.x_max) / 2);
+        let y = match position {
+            MarkPosition::Above => ink.map_or(height, |ink| ink.y_max.max(height)),
+            MarkPosition::Below => ink.map_or(0, |ink| ink.y_min),
+        };
+        Anchor { x, y }
+    }
+
+    /// The point on `mark` which meets the glyph it attaches to.
+    fn mark_anchor(&self, mark: char) -> Anchor {
+        let (bitmap, anchor) = self.marks[&mark];
+        if let Some(anchor) = anchor {
+            return anchor;
+        }
+        let ink = self.sprite(bitmap).ink_bounds();
+        let x = ink.map_or(self.width as i16 / 2, |ink| (ink.x_min + ink.x_max) / 2);
+        let y = match MarkPosition::of(mark) {
+            MarkPosition::Above => ink.map_or(0, |ink| ink.y_min),
+            MarkPosition::Below => ink.map_or(self.height as i16, |ink| ink.y_max),
+        };
+        Anchor { x, y }
+    }
+
+    /// The point on `mark` which further marks on the same side attach
+    /// to: past its ink, in line with its own anchor.
+    fn mark_stacking_anchor(&self, mark: char) -> Option<Anchor> {
+        let (bitmap, _) = self.marks[&mark];
+        let ink = self.sprite(bitmap).ink_bounds()?;
+        let y = match MarkPosition::of(mark) {
+            MarkPosition::Above => ink.y_max,
+            MarkPosition::Below => ink.y_min,
+        };
+        Some(Anchor { x: self.mark_anchor(mark).x, y })
+    }
+
+    /// The points at which each joining form connects to its neighbours:
+    /// the right edge of its ink to the preceding letter, and the left
+    /// edge to the following one, on the baseline.
+    fn compile_cursive_connections(
+        &self,
+        glyph_map: &GlyphMap,
+    ) -> Result<Vec<CursiveConnection>, Error> {
+        let mut connections = Vec::new();
+        for (&(chr, form), &bitmap) in &self.joining_forms {
+            let (preceding, following) = form.connections();
+            if !(preceding || following) { continue; }
+            let name = joining_form_name(chr, form);
+            let Some(ink) = self.sprite(bitmap).ink_bounds() else { continue };
+            let (shift, _) = self.metrics(&name.as_str().into(), bitmap);
+            let entry = preceding.then_some(Anchor { x: ink.x_max + shift, y: 0 });
+            let exit = following.then_some(Anchor { x: ink.x_min + shift, y: 0 });
+            connections.push((glyph_map.resolve(&name.into())?, entry, exit));
+        }
+        Ok(connections)
+    }
+
+    /// The mark (`mark`) and mark-to-mark (`mkmk`) attachments: marks
+    /// attach to every other sprite glyph, and to marks on the same side.
+    fn compile_mark_attachments(&self, glyph_map: &GlyphMap) -> Result<Option<(MarkAttachment, MarkAttachment)>, Error> {
+        if self.marks.is_empty() { return Ok(None); }
+        let positions = [MarkPosition::Above, MarkPosition::Below];
+        let mut marks = Vec::new();
+        let mut stacking = Vec::new();
+        for &mark in self.marks.keys() {
+            let glyph = glyph_map.resolve(&mark.into())?;
+            let position = MarkPosition::of(mark);
+            marks.push((glyph, position.class(), self.mark_anchor(mark)));
+            let anchors = positions.map(|other| {
+                if other == position { self.mark_stacking_anchor(mark) } else { None }
+            });
+            stacking.push((glyph, anchors.to_vec()));
+        }
+
+        let mut bases = Vec::new();
+        for &chr in self.glyphs.keys() {
+            if self.marks.contains_key(&chr) { continue; }
+            let anchors = positions.map(|position| Some(self.attachment(chr, position)));
+            bases.push((glyph_map.resolve(&chr.into())?, anchors.to_vec()));
+        }
+        for (name, bitmap) in self.named_glyphs.iter().cloned().chain(self.figure_alternates()) {
+            let anchors = positions.map(|position| Some(self.named_attachment(&name, bitmap, position)));
+            bases.push((glyph_map.resolve(&name.into())?, anchors.to_vec()));
+        }
+        for (chr, _) in self.mirrored_chars() {
+            if self.marks.contains_key(&chr) { continue; }
+            let anchors = positions.map(|position| Some(self.mirrored_attachment(chr, position)));
+            bases.push((glyph_map.resolve(&mirrored_name(chr).into())?, anchors.to_vec()));
+        }
+        // Further marks on a composite stack past the marks it's made with.
+        for chr in unicode::precomposed_chars() {
+            if self.glyphs.contains_key(&chr) || self.marks.contains_key(&chr) { continue; }
+            let Some(Composition { above, below, .. }) = self.compose(chr) else { continue };
+            bases.push((glyph_map.resolve(&chr.into())?, vec![Some(above), Some(below)]));
+        }
+        Ok(Some((MarkAttachment::new(marks.clone(), bases), MarkAttachment::new(marks, stacking))))
+    }
+}
+
+/// Build the legacy kerning table, which has no classes: each pair
+/// of classes is spelled out as pairs of glyphs. Pixels map one-to-one
+/// onto font units, so adjustments need no scaling.
+fn build_kern(kern_pairs: &[(GlyphId, GlyphId, i16)], kern_classes: Option<&PairClasses>) -> Option<Kern> {
+    let class_pairs = kern_classes.into_iter().flat_map(|classes| {
+        classes.first.iter().zip(&classes.values).flat_map(|(lefts, values)| {
+            classes.second.iter().zip(values).flat_map(move |(rights, &value)| {
+                lefts.iter().flat_map(move |&left| rights.iter().map(move |&right| (left, right, value)))
+            })
+        })
+    });
+    Kern::new(class_pairs.chain(kern_pairs.iter().copied()))
+}
+
+/// A precomposed char, laid out from its base and marks.
+struct Composition {
+    /// Each component, and the offset to draw it at.
+    placements: Vec<(char, i16, i16)>,
+    /// Where further marks attach above and below: past the marks
+    /// already placed on each side, if any.
+    above: Anchor,
+    below: Anchor,
+}
+
+/// The size of superscripts or subscripts, relative to the glyphs
+/// they're forms of, and how far their baseline is raised.
+struct ScriptMetrics {
+    x_scale: f64,
+    y_scale: f64,
+    y_offset: i16,
+}
+
+/// Numbers distinct classes of glyphs, in the order they're first seen.
+#[derive(Default)]
+pub(crate) struct ClassNumbering {
+    pub classes: Vec<Vec<GlyphId>>,
+    /// The class each glyph is in.
+    glyph_classes: HashMap<GlyphId, usize>,
+}
+
+impl ClassNumbering {
+    /// The number of the class of `glyphs`, failing if any of them is
+    /// already in another class.
+    pub fn number(&mut self, glyphs: &[GlyphRef], glyph_map: &GlyphMap) -> Result<usize, Error> {
+        let mut class = Vec::with_capacity(glyphs.len());
+        for glyph in glyphs {
+            class.push((glyph_map.resolve(glyph)?, glyph));
+        }
+        class.sort_unstable_by_key(|&(id, _)| id);
+        class.dedup_by_key(|&mut (id, _)| id);
+        let ids: Vec<GlyphId> = class.iter().map(|&(id, _)| id).collect();
+        if let Some(idx) = self.classes.iter().position(|existing| *existing == ids) {
+            return Ok(idx);
+        }
+        let idx = self.classes.len();
+        for (id, glyph) in class {
+            if self.glyph_classes.insert(id, idx).is_some() {
+                return Err(Error::ConflictingKernClass(glyph.clone()));
+            }
+        }
+        self.classes.push(ids);
+        Ok(idx)
+    }
+}
+
+/// Resolves `GlyphRef`s to the glyphs of the font being built.
+pub(crate) struct GlyphMap {
+    chars: HashMap<char, GlyphId>,
+    names: HashMap<String, GlyphId>,
+}
+
+impl GlyphMap {
+    fn get(&self, glyph: &GlyphRef) -> Option<GlyphId> {
+        match glyph {
+            GlyphRef::Char(chr) => self.chars.get(chr).copied(),
+            GlyphRef::Name(name) => self.names.get(name).copied(),
+        }
+    }
+
+    pub fn resolve(&self, glyph: &GlyphRef) -> Result<GlyphId, Error> {
+        self.get(glyph).ok_or_else(|| match glyph {
+            &GlyphRef::Char(chr) => Error::UnknownChar(chr),
+            GlyphRef::Name(_) => Error::UnknownGlyph(glyph.clone()),
+        })
+    }
+}
+
+/// Sprites and a font for tests to build on.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::FontBuilder;
+
+    pub const BLOCK: &[u8] = &[0xFF; 8];
+    pub const BAR: &[u8] = &[0x00, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00, 0x00];
+
+    /// An 8x8 font with the glyphs "abcfi", each drawn as a bar.
+    pub fn base() -> FontBuilder<'static> {
+        let mut builder = FontBuilder::new(8, 8, BLOCK);
+        builder.glyphs("abcfi".chars().map(|chr| (chr, BAR)));
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fixtures::{base, BAR, BLOCK};
+
+    #[test]
+    fn glyph_order_pins_named_glyphs() {
+        let mut builder = base();
+        builder.named_glyph("a.alt", BAR).glyph_order([".notdef", "b", "a.alt"]);
+        let order = builder.build().unwrap().glyph_order();
+        assert_eq!(order[..3], [".notdef", "b", "a.alt"]);
+        assert!(order[3..].iter().any(|name| name == "a"));
+    }
+
+    #[test]
+    fn glyph_order_must_start_with_notdef() {
+        let mut builder = base();
+        builder.glyph_order(["a", ".notdef"]);
+        assert_eq!(builder.build().err(), Some(Error::MisplacedNotdef));
+    }
+
+    #[test]
+    fn glyph_names_must_be_unique() {
+        let mut builder = base();
+        builder.named_glyph("a.alt", BAR).named_glyph("a.alt", BLOCK);
+        assert_eq!(builder.build().err(), Some(Error::DuplicateGlyphName("a.alt".to_string())));
+
+        let mut builder = base();
+        builder.glyph_order([".notdef", "a", "a"]);
+        assert_eq!(builder.build().err(), Some(Error::DuplicateGlyphName("a".to_string())));
+    }
+
+    #[test]
+    fn glyph_order_file_must_be_readable() {
+        let mut builder = base();
+        let result = builder.glyph_order_file("/nonexistent/glyph-order.txt");
+        assert!(matches!(result.err(), Some(Error::UnreadableFile(path, _)) if path == Path::new("/nonexistent/glyph-order.txt")));
+    }
+
+    #[test]
+    fn ligature_rules_must_not_repeat() {
+        let mut builder = base();
+        builder.ligature("fi", BLOCK).ligature_rule(['f', 'i'], "f_i");
+        assert_eq!(builder.build().err(), Some(Error::DuplicateLigature(vec!['f'.into(), 'i'.into()])));
+    }
+
+    #[test]
+    fn ligature_rules_must_not_conflict() {
+        let mut builder = base();
+        builder.named_glyph("f_i.alt", BLOCK).ligature("fi", BLOCK).ligature_rule(['f', 'i'], "f_i.alt");
+        assert_eq!(builder.build().err(), Some(Error::ConflictingLigature(vec!['f'.into(), 'i'.into()])));
+    }
+
+    #[test]
+    fn ligature_components_must_be_in_the_font() {
+        let mut builder = base();
+        builder.named_glyph("f_x", BLOCK).ligature_rule(['f', 'x'], "f_x");
+        assert_eq!(builder.build().err(), Some(Error::UnknownChar('x')));
+
+        let mut builder = base();
+        builder.named_glyph("f_i", BLOCK).ligature_rule(["f", "i.alt"], "f_i");
+        assert_eq!(builder.build().err(), Some(Error::UnknownGlyph("i.alt".into())));
+
+        let mut builder = base();
+        builder.named_glyph("f_i", BLOCK).ligature_rule(Vec::<char>::new(), "f_i");
+        assert_eq!(builder.build().err(), Some(Error::EmptyLigature("f_i".into())));
+    }
+
+    #[test]
+    fn cycled_alternates_rotate_through_their_forms() {
+        let mut builder = base();
+        builder.cycle_alternates('a', [BLOCK, BLOCK]).cycle_alternates('b', [BLOCK]);
+        let rules: Vec<_> = builder.cycling_rules().map(|rule| {
+            let [input] = &rule.input[..] else { panic!("expected one input position") };
+            (rule.backtrack, input.substitutions.clone())
+        }).collect();
+        let class = |glyphs: &[GlyphRef]| vec![glyphs.to_vec()];
+        let subs = |pairs: &[(char, &str)]| -> Vec<Substitution> {
+            pairs.iter().map(|&(chr, form)| (chr.into(), form.into())).collect()
+        };
+        assert_eq!(rules, [
+            // After each default form, each char takes its first alternate...
+            (class(&['a'.into(), 'b'.into()]), subs(&[('a', "a.alt1"), ('b', "b.alt1")])),
+            // ...then its second, or its default again after its last...
+            (class(&["a.alt1".into(), "b.alt1".into()]), subs(&[('a', "a.alt2")])),
+            // ...counting on from a longer cycle's later forms.
+            (class(&["a.alt2".into()]), subs(&[('b', "b.alt1")])),
+        ]);
+    }
+
+    const PAREN: &[u8] = &[0x00, 0x20, 0x40, 0x40, 0x40, 0x20, 0x00, 0x00];
+    const ACUTE: &[u8] = &[0x08, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+    /// Each glyph marks attach to, by name, and its anchors above and below.
+    fn mark_bases(font: &Font) -> HashMap<String, Vec<Option<Anchor>>> {
+        let names = font.glyph_order();
+        font.gpos.as_ref().unwrap().lookups()
+            .flat_map(|(_, subtables)| subtables)
+            .find_map(|subtable| match subtable {
+                PosSubtable::MarkBasePos(attachment) => Some(&attachment.bases),
+                _ => None,
+            })
+            .unwrap()
+            .iter()
+            .map(|(glyph, anchors)| (names[glyph.0 as usize].clone(), anchors.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn marks_attach_to_every_kind_of_base() {
+        let mut builder = base();
+        builder.glyph('(', PAREN).glyph('1', BAR)
+            .mark('\u{0301}', ACUTE, None)
+            .mirrored_forms()
+            .figures(Figures::Proportional);
+        let bases = mark_bases(&builder.build().unwrap());
+
+        let above = |name: &str| bases[name][0].unwrap();
+        assert_eq!(above("parenleft"), Anchor { x: 2, y: 7 });
+        assert_eq!(above("parenleft.rtlm"), Anchor { x: 6, y: 7 });
+        assert!(bases.contains_key("one.tnum"));
+        // The acute on 'á' is two pixels tall, past the top of the 'a'.
+        assert_eq!(above("aacute"), Anchor { x: above("a").x, y: above("a").y + 2 });
+    }
+
+    #[test]
+    fn ink_gap_counts_neighbouring_rows() {
+        let left = [Some((0, 3)), None, None];
+        let right = [None, Some((1, 4)), None];
+        // Row 0 of `left` faces row 1 of `right`, diagonally.
+        assert_eq!(ink_gap(&left, &right, 5), Some(3));
+        let right = [None, None, Some((0, 2))];
+        assert_eq!(ink_gap(&left, &right, 5), None);
+        // The closest rows decide, even overlapping ones.
+        let left = [Some((0, 3)), Some((0, 7))];
+        let right = [Some((2, 4)), Some((0, 4))];
+        assert_eq!(ink_gap(&left, &right, 6), Some(-1));
+    }
+
+    #[test]
+    fn auto_kerning_closes_gaps_to_the_target() {
+        const ELL: &[u8] = &[0x00, 0x80, 0x80, 0x80, 0x80, 0xF0, 0x00, 0x00];
+        let mut builder = FontBuilder::new(8, 8, BLOCK);
+        builder.glyph('L', ELL).glyph('i', BAR);
+        let pairs = builder.auto_kern_pairs(&AutoKerning::new(2).class(['L', 'i']));
+        // 'L' then 'i': the foot of the 'L' ends 4 pixels in, and the
+        // 'i' starts 3 pixels into the next cell, 7 pixels apart.
+        assert!(pairs.contains(&('L', 'i', -5)));
+        // 'i' then 'L': 4 pixels apart.
+        assert!(pairs.contains(&('i', 'L', -2)));
+
+        let pairs = builder.auto_kern_pairs(&AutoKerning::new(2).class(['L', 'i']).threshold(3));
+        assert!(pairs.contains(&('L', 'i', -5)));
+        assert!(!pairs.contains(&('i', 'L', -2)));
+    }
+
+    #[test]
+    fn composites_are_bounded_by_their_components_ink() {
+        let mut builder = base();
+        builder.mark('\u{0301}', ACUTE, None).spacing(Spacing::new(1, 1));
+        let font = builder.build().unwrap();
+        let id = |name: &str| GlyphId(font.glyph_order().iter().position(|glyph| glyph == name).unwrap() as u16);
+
+        // The bar of the 'a' is shifted to x = 1, and the acute over it
+        // reaches a pixel further left and two above.
+        let bounds = font.glyf.bounds(id("aacute")).unwrap();
+        assert_eq!(font.glyf.bounds(id("a")), Some(Rect { x_min: 1, y_min: 2, x_max: 2, y_max: 7 }));
+        assert_eq!(bounds, Rect { x_min: 0, y_min: 2, x_max: 2, y_max: 9 });
+        assert_eq!(font.hmtx.left_side_bearing(id("aacute")), bounds.x_min);
+    }
+
+    #[test]
+    fn only_fixed_advances_are_monospaced() {
+        let font = base().build().unwrap();
+        assert!(font.post.is_monospace());
+        let Os2::Version5(os2) = &font.os2;
+        assert_eq!(os2.panose[3], 9);
+        assert_eq!(os2.x_avg_char_width, 8);
+
+        let mut builder = base();
+        builder.glyph('m', BLOCK).spacing(Spacing::new(1, 1));
+        let font = builder.build().unwrap();
+        assert!(!font.post.is_monospace());
+        let Os2::Version5(os2) = &font.os2;
+        assert_eq!(os2.panose, [0; 10]);
+        // Five bars 3 pixels wide, and the 10 pixel 'm' and .notdef.
+        assert_eq!(os2.x_avg_char_width, 5);
+    }
+
+    #[test]
+    fn fractions_reuse_the_numerator_and_denominator_lookups() {
+        let mut builder = base();
+        builder.glyphs([('/', BAR), ('1', BAR), ('2', BAR)])
+            .fractions([('1', BAR, BAR), ('2', BAR, BAR)], BAR);
+        let font = builder.build().unwrap();
+        let gsub = font.gsub.as_ref().unwrap();
+        let feature = |tag: &[u8; 4]| gsub.feature_lookups((*b"DFLT", None), *tag).unwrap();
+        let [numr] = feature(b"numr")[..] else { panic!("expected one numr lookup") };
+        let [dnom] = feature(b"dnom")[..] else { panic!("expected one dnom lookup") };
+
+        // The slash, then the numerators, then the denominators, which
+        // substitute digits with the numr and dnom lookups.
+        let nested = |lookup: LookupId| -> Vec<Vec<(u16, LookupId)>> {
+            gsub.lookup(lookup).1.iter().map(|subtable| match subtable {
+                SubstSubtable::ChainContextSubst(rule) => rule.lookups.clone(),
+                _ => panic!("expected contextual rules"),
+            }).collect()
+        };
+        let [slash, numerators, denominators] = feature(b"frac")[..] else { panic!("expected three frac lookups") };
+        assert!(nested(slash).concat().iter().all(|&(_, lookup)| lookup != numr && lookup != dnom));
+        assert_eq!(nested(numerators), vec![vec![(0, numr)]; MAX_NUMERATOR_DIGITS]);
+        assert_eq!(nested(denominators), [[(0, dnom)]]);
+    }
+
+    #[test]
+    fn script_forms_set_the_os2_script_metrics() {
+        // One pixel wide and two tall, from y = 5 and y = 0, where the
+        // bar is five tall from y = 2.
+        const HIGH: &[u8] = &[0x00, 0x10, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00];
+        const LOW: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x10];
+        let mut builder = base();
+        builder.superscript('a', HIGH).subscript('a', LOW);
+        let font = builder.build().unwrap();
+        let Os2::Version5(os2) = &font.os2;
+        assert_eq!(os2.y_superscript_x_size, 16);
+        assert_eq!(os2.y_subscript_x_size, 16);
+        // 2/5 of the 16 unit em.
+        assert_eq!(os2.y_superscript_y_size, 6);
+        assert_eq!(os2.y_subscript_y_size, 6);
+        // The bar's scaled bottom, 0.8, is raised to 5 and lowered to 0.
+        assert_eq!(os2.y_superscript_y_offset, 4);
+        assert_eq!(os2.y_subscript_y_offset, 1);
+    }
+
+    #[test]
+    fn ordinals_raise_letters_after_digits() {
+        let mut builder = base();
+        builder.glyph('1', BAR).superscript('a', BAR).superscript('1', BAR);
+        let font = builder.build().unwrap();
+        let gsub = font.gsub.as_ref().unwrap();
+        let names = font.glyph_order();
+        let named = |glyphs: &[GlyphId]| -> Vec<&str> { glyphs.iter().map(|glyph| names[glyph.0 as usize].as_str()).collect() };
+
+        let [ordn] = gsub.feature_lookups((*b"DFLT", None), *b"ordn").unwrap()[..] else { panic!("expected one ordn lookup") };
+        let [SubstSubtable::ChainContextSubst(rule)] = gsub.lookup(ordn).1 else { panic!("expected one contextual rule") };
+        // Only letters are raised: after a digit, or a raised letter.
+        assert_eq!(rule.backtrack.iter().map(|class| named(class)).collect::<Vec<_>>(), [["one", "a.sups"]]);
+        assert_eq!(rule.input.iter().map(|class| named(class)).collect::<Vec<_>>(), [["a"]]);
+        let [(0, nested)] = rule.lookups[..] else { panic!("expected one nested lookup") };
+        let [SubstSubtable::SingleSubst(subs)] = gsub.lookup(nested).1 else { panic!("expected single substitutions") };
+        let subs: Vec<_> = subs.iter().map(|&(glyph, form)| named(&[glyph, form])).collect();
+        assert_eq!(subs, [["a", "a.sups"]]);
+    }
+
+    #[test]
+    fn hhea_agrees_with_the_spaced_metrics() {
+        let mut builder = base();
+        builder.glyph('m', BLOCK)
+            .spacing(Spacing::new(1, 2).tracking(-1).side_bearings('c', 0, 1));
+        let font = builder.build().unwrap();
+        let (mut min_lsb, mut min_rsb, mut max_extent) = (i16::MAX, i16::MAX, i16::MIN);
+        for glyph in (0..font.glyph_order().len() as u16).map(GlyphId) {
+            let Some(rect) = font.glyf.bounds(glyph) else { continue };
+            let lsb = font.hmtx.left_side_bearing(glyph);
+            let extent = lsb + rect.x_max - rect.x_min;
+            min_lsb = min_lsb.min(lsb);
+            min_rsb = min_rsb.min(font.hmtx.advance_width(glyph) as i16 - extent);
+            max_extent = max_extent.max(extent);
+        }
+        // The 'c' is padded with nothing on the left, and one pixel on
+        // the right, which the tracking takes back.
+        assert_eq!((min_lsb, min_rsb, max_extent), (0, 0, 9));
+        assert_eq!(font.hhea.min_left_side_bearing, min_lsb);
+        assert_eq!(font.hhea.min_right_side_bearing, min_rsb);
+        assert_eq!(font.hhea.x_max_extent, max_extent);
+    }
+
+    #[test]
+    fn stacked_marks_raise_the_font_bounds() {
+        let mut builder = base();
+        builder.glyph('u', BAR)
+            .mark('\u{0308}', ACUTE, None)
+            .mark('\u{0301}', ACUTE, None);
+        let font = builder.build().unwrap();
+        // 'ǘ' stacks two marks, each two pixels tall, on the bar's top
+        // at y = 7.
+        assert_eq!(font.head.rect.y_max, 11);
+        assert_eq!(font.head.rect.y_min, 0);
+        assert_eq!(font.hhea.ascent, 11);
+        let Os2::Version5(os2) = &font.os2;
+        assert_eq!((os2.s_typo_ascender, os2.us_win_ascent), (11, 11));
+        assert_eq!((os2.s_typo_descender, os2.us_win_descent), (0, 0));
+    }
+
+    #[test]
+    fn variants_are_named_in_their_feature_params() {
+        let mut builder = base();
+        builder.glyph('0', BAR).named_glyph("zero.alt", BLOCK).named_glyph("a.alt", BLOCK)
+            .stylistic_set(1, "Open a", [('a', "a.alt")])
+            .character_variant(2, "Slashed zero", [('0', "zero.alt")]);
+        let font = builder.build().unwrap();
+        let gsub = font.gsub.as_ref().unwrap();
+        let feature = |tag: &[u8; 4]| gsub.feature_bytes((*b"DFLT", None), *tag).unwrap();
+
+        // The params follow the feature's one lookup index, 6 bytes in.
+        let ss01 = feature(b"ss01");
+        assert_eq!(ss01[..4], [0, 6, 0, 1]);
+        let ss01_name = u16::from_be_bytes([ss01[8], ss01[9]]);
+        assert_eq!(ss01[6..8], [0, 0]);
+        assert_eq!(font.name.texts(ss01_name), ["Open a", "Open a"]);
+
+        let cv02 = feature(b"cv02");
+        assert_eq!(cv02[..4], [0, 6, 0, 1]);
+        let cv02_name = u16::from_be_bytes([cv02[8], cv02[9]]);
+        assert_ne!(cv02_name, ss01_name);
+        assert_eq!(font.name.texts(cv02_name), ["Slashed zero", "Slashed zero"]);
+        // No tooltip, sample or parameter names, and the one char, '0'.
+        assert_eq!(cv02[10..], [0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, b'0']);
+    }
+
+    #[test]
+    fn aalt_offers_every_alternate() {
+        let mut builder = base();
+        builder.named_glyph("a.salt", BLOCK).named_glyph("a.ss01", BLOCK).named_glyph("b.cv01", BLOCK)
+            .alternates('a', ["a.salt"])
+            .cycle_alternates('a', [BLOCK])
+            .stylistic_set(1, "Set", [('a', "a.ss01")])
+            .character_variant(1, "Variant", [('b', "b.cv01")]);
+        let font = builder.build().unwrap();
+        let gsub = font.gsub.as_ref().unwrap();
+        let names = font.glyph_order();
+        let named = |glyph: GlyphId| names[glyph.0 as usize].as_str();
+
+        let [aalt] = gsub.feature_lookups((*b"DFLT", None), *b"aalt").unwrap()[..] else { panic!("expected one aalt lookup") };
+        let [SubstSubtable::AlternateSubst(alternates)] = gsub.lookup(aalt).1 else { panic!("expected alternate substitutions") };
+        let alternates: Vec<(&str, Vec<&str>)> = alternates.iter()
+            .map(|(glyph, alternates)| (named(*glyph), alternates.iter().copied().map(named).collect()))
+            .collect();
+        // Stylistic alternates, then cycled ones, then the variants.
+        assert_eq!(alternates, [
+            ("a", vec!["a.salt", "a.alt1", "a.ss01"]),
+            ("b", vec!["b.cv01"]),
+        ]);
+    }
+
+    #[test]
+    fn gdef_classes_glyphs_and_places_carets() {
+        let mut builder = base();
+        builder.glyphs([('-', BAR), ('>', BAR), ('!', BAR), ('=', BAR), ('\u{0302}', BAR)])
+            .mark('\u{0301}', ACUTE, None)
+            .ligature("->", BLOCK)
+            .ligature("!=", BLOCK);
+        let font = builder.build().unwrap();
+        let names = font.glyph_order();
+        let glyph = |name: &str| GlyphId(names.iter().position(|other| other == name).unwrap() as u16);
+        let gdef = &font.gdef;
+
+        assert_eq!(gdef.glyph_class(glyph("a")), GlyphClass::Base as u16);
+        assert_eq!(gdef.glyph_class(glyph("hyphen_greater")), GlyphClass::Ligature as u16);
+        assert_eq!(gdef.glyph_class(glyph("exclam_equal")), GlyphClass::Ligature as u16);
+        assert_eq!(gdef.glyph_class(glyph("uni0301")), GlyphClass::Mark as u16);
+        // A combining char drawn as a spacing glyph isn't a mark.
+        assert_eq!(gdef.glyph_class(glyph("uni0302")), GlyphClass::Base as u16);
+
+        // One caret, halfway across each two-component ligature.
+        assert_eq!(gdef.carets(glyph("hyphen_greater")), Some(&[4][..]));
+        assert_eq!(gdef.carets(glyph("exclam_equal")), Some(&[4][..]));
+        assert_eq!(gdef.carets(glyph("a")), None);
+    }
+
+    #[test]
+    fn small_caps_replace_both_cases() {
+        // Three pixels tall, under the x-height.
+        const SMALL: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x10, 0x10, 0x10, 0x00];
+        let mut builder = base();
+        builder.glyph('A', BAR).glyph('B', BAR).small_caps([('a', SMALL), ('B', SMALL)])
+            .mark('\u{0301}', ACUTE, None);
+        let font = builder.build().unwrap();
+        let gsub = font.gsub.as_ref().unwrap();
+        let names = font.glyph_order();
+        let named = |glyph: GlyphId| names[glyph.0 as usize].as_str();
+        assert!(names.iter().any(|name| name == "a.sc") && names.iter().any(|name| name == "b.sc"));
+
+        let singles = |tag: &[u8; 4]| -> Vec<(&str, &str)> {
+            let [lookup] = gsub.feature_lookups((*b"DFLT", None), *tag).unwrap()[..] else { panic!("expected one lookup") };
+            let [SubstSubtable::SingleSubst(subs)] = gsub.lookup(lookup).1 else { panic!("expected single substitutions") };
+            subs.iter().map(|&(glyph, small_cap)| (named(glyph), named(small_cap))).collect()
+        };
+        assert_eq!(singles(b"smcp"), [("a", "a.sc"), ("b", "b.sc")]);
+        assert_eq!(singles(b"c2sc"), [("A", "a.sc"), ("B", "b.sc")]);
+
+        // Marks sit on the x-height over the small caps, or on the small
+        // cap height when it's given.
+        assert_eq!(mark_bases(&font)["a.sc"][0].unwrap().y, 5);
+        builder.small_cap_height(6);
+        assert_eq!(mark_bases(&builder.build().unwrap())["a.sc"][0].unwrap().y, 6);
+    }
+
+    #[test]
+    fn tabular_and_proportional_figures_swap() {
+        // Three pixels wide, to the bar's one.
+        const ZERO: &[u8] = &[0x00, 0x38, 0x28, 0x28, 0x28, 0x38, 0x00, 0x00];
+        let mut builder = base();
+        builder.glyph('0', ZERO).glyph('1', BAR)
+            .spacing(Spacing::new(1, 1))
+            .figures(Figures::Proportional);
+        let font = builder.build().unwrap();
+        let gsub = font.gsub.as_ref().unwrap();
+        let names = font.glyph_order();
+        let glyph = |name: &str| GlyphId(names.iter().position(|other| other == name).unwrap() as u16);
+        let named = |glyph: GlyphId| names[glyph.0 as usize].as_str();
+        let advance = |name: &str| font.hmtx.advance_width(glyph(name));
+
+        // Proportional figures keep their ink spacing, and tabular ones
+        // are as wide as the widest, with their ink centered.
+        assert_eq!((advance("zero"), advance("one")), (5, 3));
+        assert_eq!((advance("zero.tnum"), advance("one.tnum")), (5, 5));
+        assert_eq!(font.hmtx.left_side_bearing(glyph("one.tnum")), 2);
+        for name in ["zero", "one", "zero.tnum", "one.tnum"] {
+            assert_eq!(font.glyf.bounds(glyph(name)).unwrap().x_min, font.hmtx.left_side_bearing(glyph(name)));
+        }
+
+        let singles = |tag: &[u8; 4]| -> Vec<(&str, &str)> {
+            let [lookup] = gsub.feature_lookups((*b"DFLT", None), *tag).unwrap()[..] else { panic!("expected one lookup") };
+            let [SubstSubtable::SingleSubst(subs)] = gsub.lookup(lookup).1 else { panic!("expected single substitutions") };
+            subs.iter().map(|&(from, to)| (named(from), named(to))).collect()
+        };
+        let mut tnum = singles(b"tnum");
+        tnum.sort_unstable();
+        assert_eq!(tnum, [("one", "one.tnum"), ("zero", "zero.tnum")]);
+        let mut pnum = singles(b"pnum");
+        pnum.sort_unstable();
+        assert_eq!(pnum, [("one.tnum", "one"), ("zero.tnum", "zero")]);
+
+        // The average width counts the alternates, as hmtx has them.
+        let advances: Vec<u16> = (0..names.len() as u16).map(|glyph| font.hmtx.advance_width(GlyphId(glyph)))
+            .filter(|&advance| advance != 0)
+            .collect();
+        let total: u16 = advances.iter().sum();
+        let count = advances.len() as u16;
+        let Os2::Version5(os2) = &font.os2;
+        assert_eq!(os2.x_avg_char_width, ((total + count / 2) / count) as i16);
+    }
+
+    #[test]
+    fn blank_glyphs_take_no_room() {
+        let mut builder = base();
+        builder.glyph(' ', &[0; 8]);
+        let font = builder.build().unwrap();
+        let names = font.glyph_order();
+        let glyph = |name: &str| GlyphId(names.iter().position(|other| other == name).unwrap() as u16);
+        assert_eq!(names[..3], [".notdef", ".null", "nonmarkingreturn"]);
+
+        for name in [".null", "nonmarkingreturn", "space"] {
+            assert_eq!(font.loca.len(glyph(name)), 0, "{name}");
+        }
+        assert_ne!(font.loca.len(glyph("a")), 0);
+
+        assert_eq!(font.cmap.glyph('\0'), Some(glyph(".null")));
+        assert_eq!(font.cmap.glyph('\r'), Some(glyph("nonmarkingreturn")));
+        assert_eq!(font.cmap.glyph('\u{00A0}'), Some(glyph("space")));
+        assert_eq!(font.cmap.glyph(' '), Some(glyph("space")));
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+use std::path::PathBuf;
+use crate::GlyphRef;
+
+/// Errors which can occur while building a font.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The character can't be represented in the font's character map.
+    /// Currently only the Basic Multilingual Plane is supported.
+    UnsupportedChar(char),
+    /// The character was referenced, but has no glyph in the font.
+    UnknownChar(char),
+    /// The character was given both a glyph and an alias.
+    DuplicateChar(char),
+    /// More than one glyph was given the same name.
+    DuplicateGlyphName(String),
+    /// The glyph name isn't a valid PostScript glyph name.
+    InvalidGlyphName(String),
+    /// The glyph was referenced, but isn't in the font.
+    UnknownGlyph(GlyphRef),
+    /// The pinned glyph order doesn't start with `.notdef`, which must be glyph 0.
+    MisplacedNotdef,
+    /// The ligature has no components to replace.
+    EmptyLigature(GlyphRef),
+    /// The same ligature was given more than once.
+    DuplicateLigature(Vec<GlyphRef>),
+    /// The same sequence was given ligatures with different replacements.
+    ConflictingLigature(Vec<GlyphRef>),
+    /// The characters are mapped too sparsely to fit in the character
+    /// map's 16-bit length.
+    CharMapOverflow,
+    /// The substitution or positioning rules don't fit in the GSUB or
+    /// GPOS table, even after splitting them up and moving them behind
+    /// 32-bit offsets.
+    OffsetOverflow,
+    /// The feature tag isn't one which can be built, eg `ss21`.
+    UnsupportedFeature(String),
+    /// The glyph was given more than one substitution within the feature.
+    DuplicateSubstitution(String, GlyphRef),
+    /// The contextual rule has no input glyphs to match.
+    EmptyContextualInput,
+    /// The OpenType tag isn't one to four printable ASCII characters.
+    InvalidTag(String),
+    /// The glyph was given an empty sequence of glyphs to replace it.
+    EmptySequence(GlyphRef),
+    /// The glyph is in two different kerning classes on the same side.
+    ConflictingKernClass(GlyphRef),
+    /// The file couldn't be read, for the reason given.
+    UnreadableFile(PathBuf, String),
+    /// The feature file can't be parsed or compiled, at the given line
+    /// and column.
+    InvalidFeatures { line: usize, column: usize, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsupportedChar(c) =>
+                write!(f, "character {c:?} (U+{:04X}) cannot be mapped", *c as u32),
+            Error::UnknownChar(c) =>
+                write!(f, "character {c:?} (U+{:04X}) has no glyph", *c as u32),
+            Error::DuplicateChar(c) =>
+                write!(f, "character {c:?} (U+{:04X}) has both a glyph and an alias", *c as u32),
+            Error::DuplicateGlyphName(name) =>
+                write!(f, "more than one glyph is named {name:?}"),
+            Error::InvalidGlyphName(name) =>
+                write!(f, "{name:?} is not a valid glyph name"),
+            Error::UnknownGlyph(glyph) =>
+                write!(f, "glyph {glyph} is not in the font"),
+            Error::MisplacedNotdef =>
+                write!(f, "the glyph order must start with .notdef"),
+            Error::EmptyLigature(glyph) =>
+                write!(f, "ligature for glyph {glyph} has no components"),
+            Error::DuplicateLigature(components) =>
+                write!(f, "ligature {} is given more than once", Sequence(components)),
+            Error::ConflictingLigature(components) =>
+                write!(f, "ligature {} is given conflicting replacements", Sequence(components)),
+            Error::CharMapOverflow =>
+                write!(f, "too many scattered characters to fit in the character map"),
+            Error::OffsetOverflow =>
+                write!(f, "too many substitution or positioning rules to fit in the font"),
+            Error::UnsupportedFeature(tag) =>
+                write!(f, "feature {tag:?} is not supported"),
+            Error::DuplicateSubstitution(tag, glyph) =>
+                write!(f, "glyph {glyph} is substituted more than once in feature {tag:?}"),
+            Error::EmptyContextualInput =>
+                write!(f, "contextual rule has no input glyphs"),
+            Error::InvalidTag(tag) =>
+                write!(f, "{tag:?} is not a valid OpenType tag"),
+            Error::EmptySequence(glyph) =>
+                write!(f, "glyph {glyph} is replaced with no glyphs"),
+            Error::ConflictingKernClass(glyph) =>
+                write!(f, "glyph {glyph} is in more than one kerning class on the same side"),
+            Error::UnreadableFile(path, reason) =>
+                write!(f, "could not read {}: {reason}", path.display()),
+            Error::InvalidFeatures { line, column, message } =>
+                write!(f, "line {line}, column {column}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Displays a sequence of glyphs, space separated.
+struct Sequence<'a>(&'a [GlyphRef]);
+
+impl fmt::Display for Sequence<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, glyph) in self.0.iter().enumerate() {
+            if idx > 0 { f.write_str(" ")?; }
+            write!(f, "{glyph}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1355 @@
+// A compiler for a subset of the Adobe feature file syntax: glyph
+// classes, substitution and positioning rules, lookup and feature
+// blocks, and script and language statements.
+// https://adobe-type-tools.github.io/afdko/OpenTypeFeatureFileSpecification.html
+
+use crate::{Anchor, GlyphId, GlyphRef};
+use crate::builder::{to_tag, ClassNumbering, GlyphMap};
+use crate::error::Error;
+use crate::tables::{
+    ChainRule, CursiveConnection, GPosBuilder, GSubBuilder, LayoutBuilder, Ligature, LookupFlags, LookupId,
+    LookupSubtable, PairClasses, PosSubtable, SubstSubtable,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// The language tag standing for a script's default language system.
+const DEFAULT_LANGUAGE: [u8; 4] = *b"dflt";
+
+/// Statements which are part of the syntax, but which can't be compiled.
+const UNSUPPORTED: &[&str] = &[
+    "anchorDef", "cvParameters", "featureNames", "include", "markClass", "parameters",
+    "reversesub", "rsub", "sizemenuname", "subtable", "table", "valueRecordDef",
+];
+
+/// The lookups and features of a feature file, parsed and ready to be
+/// compiled against the glyphs of a font.
+pub(crate) struct FeatureFile {
+    /// Each script, and language tag within it, from `languagesystem`
+    /// statements.
+    language_systems: Vec<([u8; 4], [u8; 4])>,
+    /// In the order they apply.
+    lookups: Vec<Lookup>,
+    features: Vec<Feature>,
+}
+
+impl FeatureFile {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        Parser::new(source)?.file()
+    }
+
+    /// Add the file's substitution lookups to `gsub`, after those
+    /// already there, and register the features which apply them.
+    pub fn compile_gsub(&self, glyph_map: &GlyphMap, gsub: &mut GSubBuilder) -> Result<(), Error> {
+        let mut ids = vec![None; self.lookups.len()];
+        for (idx, lookup) in self.lookups.iter().enumerate() {
+            if lookup.kind().is_positioning() { continue; }
+            let subtables = match lookup.kind() {
+                LookupKind::Contextual => compile_contextual(lookup, &ids, glyph_map, gsub)?,
+                kind => {
+                    let mut substitutions = Substitutions::default();
+                    for (rule, _) in &lookup.rules {
+                        substitutions.add(rule, glyph_map)?;
+                    }
+                    vec![substitutions.subtable(kind)]
+                }
+            };
+            ids[idx] = Some(gsub.flagged_lookup(lookup.flags, subtables));
+        }
+        self.register(&ids, gsub);
+        Ok(())
+    }
+
+    /// Add the file's positioning lookups to `gpos`, after those
+    /// already there, and register the features which apply them.
+    pub fn compile_gpos(&self, glyph_map: &GlyphMap, gpos: &mut GPosBuilder) -> Result<(), Error> {
+        let mut ids = vec![None; self.lookups.len()];
+        for (idx, lookup) in self.lookups.iter().enumerate() {
+            let subtables = match lookup.kind() {
+                LookupKind::Pair => compile_pairs(lookup, glyph_map)?,
+                LookupKind::Cursive => compile_cursive(lookup, glyph_map)?,
+                _ => continue,
+            };
+            ids[idx] = Some(gpos.flagged_lookup(lookup.flags, subtables));
+        }
+        self.register(&ids, gpos);
+        Ok(())
+    }
+
+    /// Register the language systems, and each feature with the lookups
+    /// among `ids` it applies in each of them.
+    fn register<S: LookupSubtable>(&self, ids: &[Option<LookupId>], table: &mut LayoutBuilder<S>) {
+        if ids.iter().all(Option::is_none) { return; }
+        for &(script, language) in &self.language_systems {
+            table.script(script, Some(language).filter(|&language| language != DEFAULT_LANGUAGE));
+        }
+        for feature in &self.features {
+            for &(target, idx) in &feature.lookups {
+                let Some(id) = ids[idx] else { continue };
+                match target {
+                    Target::All => table.feature(feature.tag, None, &[id]),
+                    Target::LangSys(script, None) => table.script_feature(script, feature.tag, &[id]),
+                    Target::LangSys(script, Some(language)) =>
+                        table.language_feature(script, language, feature.tag, &[id]),
+                }
+            }
+        }
+    }
+}
+
+/// Where a token starts in the source, counting from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn error(self, message: impl Into<String>) -> Error {
+        Error::InvalidFeatures { line: self.line, column: self.column, message: message.into() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    /// A keyword, or a glyph name.
+    Name(String),
+    /// A glyph name escaped with a backslash, which is never a keyword.
+    Glyph(String),
+    /// A glyph class's name, without its `@`.
+    Class(String),
+    Number(i16),
+    Symbol(char),
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Name(name) if name == keyword)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Name(name) => write!(f, "`{name}`"),
+            Token::Glyph(name) => write!(f, "`\\{name}`"),
+            Token::Class(name) => write!(f, "`@{name}`"),
+            Token::Number(number) => write!(f, "`{number}`"),
+            Token::Symbol(symbol) => write!(f, "`{symbol}`"),
+        }
+    }
+}
+
+fn is_name_char(chr: char) -> bool {
+    chr.is_ascii_alphanumeric() || matches!(chr, '.' | '_' | '-')
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    idx: usize,
+    position: Position,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.idx).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let chr = self.peek()?;
+        self.idx += 1;
+        if chr == '\n' {
+            self.position = Position { line: self.position.line + 1, column: 1 };
+        } else {
+            self.position.column += 1;
+        }
+        Some(chr)
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let mut out = String::new();
+        while let Some(chr) = self.peek().filter(|&chr| pred(chr)) {
+            out.push(chr);
+            self.bump();
+        }
+        out
+    }
+
+    /// The name following a `@` or `\` at `start`.
+    fn name(&mut self, start: Position) -> Result<String, Error> {
+        let prefix = self.bump();
+        let name = self.take_while(is_name_char);
+        if name.is_empty() {
+            return Err(start.error(format!("expected a name after `{}`", prefix.unwrap_or_default())));
+        }
+        Ok(name)
+    }
+
+    /// Split the source into tokens, along with the position just past
+    /// the last of them.
+    fn tokenize(mut self) -> Result<(Vec<(Token, Position)>, Position), Error> {
+        let mut tokens = Vec::new();
+        while let Some(chr) = self.peek() {
+            let start = self.position;
+            let token = match chr {
+                _ if chr.is_whitespace() => {
+                    self.bump();
+                    continue;
+                }
+                '#' => {
+                    self.take_while(|chr| chr != '\n');
+                    continue;
+                }
+                '\\' => Token::Glyph(self.name(start)?),
+                '@' => Token::Class(self.name(start)?),
+                '-' | '0'..='9' => {
+                    let text = self.take_while(is_name_char);
+                    let number = text.parse()
+                        .map_err(|_| start.error(format!("`{text}` is not a valid number")))?;
+                    Token::Number(number)
+                }
+                _ if chr.is_ascii_alphabetic() || matches!(chr, '.' | '_') => Token::Name(self.take_while(is_name_char)),
+                '{' | '}' | '[' | ']' | ';' | '\'' | '=' | '<' | '>' => {
+                    self.bump();
+                    Token::Symbol(chr)
+                }
+                _ => return Err(start.error(format!("unexpected character {chr:?}"))),
+            };
+            tokens.push((token, start));
+        }
+        Ok((tokens, self.position))
+    }
+}
+
+/// A glyph named in the source, and where.
+#[derive(Clone, Debug)]
+struct Glyph {
+    name: String,
+    position: Position,
+}
+
+impl Glyph {
+    fn resolve(&self, glyph_map: &GlyphMap) -> Result<GlyphId, Error> {
+        glyph_map.resolve(&self.glyph_ref()).map_err(|err| self.position.error(err.to_string()))
+    }
+
+    fn glyph_ref(&self) -> GlyphRef {
+        self.name.as_str().into()
+    }
+}
+
+fn resolve_class(class: &[Glyph], glyph_map: &GlyphMap) -> Result<Vec<GlyphId>, Error> {
+    class.iter().map(|glyph| glyph.resolve(glyph_map)).collect()
+}
+
+/// A glyph or class in the sequence a rule matches.
+struct Item {
+    glyphs: Vec<Glyph>,
+    /// Whether it's a class, even if of one glyph.
+    class: bool,
+    position: Position,
+    /// Whether it's marked with `'` as part of a contextual rule's input.
+    marked: bool,
+    /// The lookups to apply at it, by name.
+    lookups: Vec<(String, Position)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LookupKind {
+    Single,
+    Multiple,
+    Alternate,
+    Ligature,
+    Contextual,
+    Pair,
+    Cursive,
+}
+
+impl LookupKind {
+    fn is_positioning(self) -> bool {
+        matches!(self, LookupKind::Pair | LookupKind::Cursive)
+    }
+}
+
+impl fmt::Display for LookupKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LookupKind::Single => "single substitution",
+            LookupKind::Multiple => "multiple substitution",
+            LookupKind::Alternate => "alternate substitution",
+            LookupKind::Ligature => "ligature substitution",
+            LookupKind::Contextual => "contextual substitution",
+            LookupKind::Pair => "pair positioning",
+            LookupKind::Cursive => "cursive positioning",
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Rule {
+    /// Each glyph and its replacement.
+    Single(Vec<(Glyph, Glyph)>),
+    Multiple(Glyph, Vec<Glyph>),
+    Alternate(Glyph, Vec<Glyph>),
+    /// The class of glyphs at each position, and the ligature which
+    /// replaces any sequence of them.
+    Ligature(Vec<Vec<Glyph>>, Glyph),
+    Contextual(Context),
+    /// Each pair of glyphs, and the adjustment to the first's advance.
+    Pairs(Vec<(Glyph, Glyph)>, i16),
+    /// A pair of classes, and the adjustment to the advance of the first.
+    ClassPair(Vec<Glyph>, Vec<Glyph>, i16),
+    /// Each glyph, and its entry and exit anchors.
+    Cursive(Vec<Glyph>, Option<Anchor>, Option<Anchor>),
+}
+
+impl Rule {
+    fn kind(&self) -> LookupKind {
+        match self {
+            Rule::Single(_) => LookupKind::Single,
+            Rule::Multiple(..) => LookupKind::Multiple,
+            Rule::Alternate(..) => LookupKind::Alternate,
+            Rule::Ligature(..) => LookupKind::Ligature,
+            Rule::Contextual(_) => LookupKind::Contextual,
+            Rule::Pairs(..) | Rule::ClassPair(..) => LookupKind::Pair,
+            Rule::Cursive(..) => LookupKind::Cursive,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Context {
+    /// In reading order, like `input` and `lookahead`.
+    backtrack: Vec<Vec<Glyph>>,
+    input: Vec<Vec<Glyph>>,
+    lookahead: Vec<Vec<Glyph>>,
+    action: Action,
+}
+
+/// What a contextual rule does to its input.
+#[derive(Debug)]
+enum Action {
+    /// Nothing: an `ignore` rule, which stops later rules matching.
+    Ignore,
+    /// A substitution given inline with `by`.
+    Inline(Box<Rule>),
+    /// Each index into the input, and the lookup to apply there.
+    Lookups(Vec<(u16, usize)>),
+}
+
+struct Lookup {
+    flags: LookupFlags,
+    /// Each rule, and where it starts. They're all of one kind.
+    rules: Vec<(Rule, Position)>,
+}
+
+impl Lookup {
+    fn kind(&self) -> LookupKind {
+        self.rules[0].0.kind()
+    }
+}
+
+/// The language systems a feature's lookups are registered for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    /// Every language system in the font.
+    All,
+    /// A script, and one of its languages or its default one.
+    LangSys([u8; 4], Option<[u8; 4]>),
+}
+
+struct Feature {
+    tag: [u8; 4],
+    /// Each lookup, by index, and where it's registered.
+    lookups: Vec<(Target, usize)>,
+    /// Which of `lookups` are only registered as defaults, from before
+    /// any `script` or `language`, or from a language's script. These
+    /// are what `exclude_dflt` removes.
+    defaults: Vec<(Target, usize)>,
+}
+
+impl Feature {
+    fn register(&mut self, targets: &[Target], lookup: usize) {
+        for &target in targets {
+            self.defaults.retain(|&entry| entry != (target, lookup));
+            if !self.lookups.contains(&(target, lookup)) {
+                self.lookups.push((target, lookup));
+            }
+        }
+    }
+
+    fn register_default(&mut self, targets: &[Target], lookup: usize) {
+        for &target in targets {
+            if !self.lookups.contains(&(target, lookup)) {
+                self.lookups.push((target, lookup));
+                self.defaults.push((target, lookup));
+            }
+        }
+    }
+
+    /// Register `lookup`, as a default if `default`.
+    fn register_as(&mut self, targets: &[Target], lookup: usize, default: bool) {
+        if default {
+            self.register_default(targets, lookup);
+        } else {
+            self.register(targets, lookup);
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    idx: usize,
+    /// The position just past the last token.
+    end: Position,
+    classes: HashMap<String, Vec<Glyph>>,
+    lookup_names: HashMap<String, usize>,
+    file: FeatureFile,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, Error> {
+        let lexer = Lexer { chars: source.chars().collect(), idx: 0, position: Position { line: 1, column: 1 } };
+        let (tokens, end) = lexer.tokenize()?;
+        Ok(Parser {
+            tokens,
+            idx: 0,
+            end,
+            classes: HashMap::new(),
+            lookup_names: HashMap::new(),
+            file: FeatureFile { language_systems: Vec::new(), lookups: Vec::new(), features: Vec::new() },
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.idx).map(|(token, _)| token)
+    }
+
+    /// The position of the next token.
+    fn position(&self) -> Position {
+        self.tokens.get(self.idx).map_or(self.end, |&(_, position)| position)
+    }
+
+    fn next(&mut self) -> Result<(Token, Position), Error> {
+        let next = self.tokens.get(self.idx).cloned()
+            .ok_or_else(|| self.end.error("unexpected end of file"))?;
+        self.idx += 1;
+        Ok(next)
+    }
+
+    /// Consume the next token if it's `token`.
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found { self.idx += 1; }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.eat(&Token::Name(keyword.to_string()))
+    }
+
+    fn expect(&mut self, token: Token) -> Result<Position, Error> {
+        let (found, position) = self.next()?;
+        if found != token {
+            return Err(position.error(format!("expected {token}, found {found}")));
+        }
+        Ok(position)
+    }
+
+    fn name(&mut self) -> Result<(String, Position), Error> {
+        match self.next()? {
+            (Token::Name(name) | Token::Glyph(name), position) => Ok((name, position)),
+            (found, position) => Err(position.error(format!("expected a name, found {found}"))),
+        }
+    }
+
+    fn tag(&mut self) -> Result<[u8; 4], Error> {
+        let (name, position) = self.name()?;
+        to_tag(&name).map_err(|err| position.error(err.to_string()))
+    }
+
+    fn number(&mut self) -> Result<i16, Error> {
+        match self.next()? {
+            (Token::Number(number), _) => Ok(number),
+            (found, position) => Err(position.error(format!("expected a number, found {found}"))),
+        }
+    }
+
+    /// Expect the name closing the block opened with `name`, and the
+    /// semicolon after it.
+    fn close_block(&mut self, name: &str) -> Result<(), Error> {
+        self.expect(Token::Symbol('}'))?;
+        let (closing, position) = self.name()?;
+        if closing != name {
+            return Err(position.error(format!("expected `{name}` to close its block, found `{closing}`")));
+        }
+        self.expect(Token::Symbol(';'))?;
+        Ok(())
+    }
+
+    fn unexpected(&mut self) -> Error {
+        match self.next() {
+            Ok((Token::Name(name), position)) if UNSUPPORTED.contains(&name.as_str()) =>
+                position.error(format!("`{name}` is not supported")),
+            Ok((found, position)) => position.error(format!("unexpected {found}")),
+            Err(err) => err,
+        }
+    }
+
+    fn file(mut self) -> Result<FeatureFile, Error> {
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Class(_) => self.class_definition()?,
+                token if token.is_keyword("languagesystem") => self.language_system()?,
+                token if token.is_keyword("lookup") => {
+                    self.next()?;
+                    let (name, position) = self.name()?;
+                    self.lookup_block(name, position, LookupFlags::empty())?;
+                }
+                token if token.is_keyword("feature") => self.feature_block()?,
+                _ => return Err(self.unexpected()),
+            }
+        }
+        Ok(self.file)
+    }
+
+    fn language_system(&mut self) -> Result<(), Error> {
+        let (_, position) = self.next()?;
+        if !self.file.features.is_empty() {
+            return Err(position.error("language systems must be given before any feature"));
+        }
+        let script = self.tag()?;
+        let language = self.tag()?;
+        self.expect(Token::Symbol(';'))?;
+        if !self.file.language_systems.contains(&(script, language)) {
+            self.file.language_systems.push((script, language));
+        }
+        Ok(())
+    }
+
+    fn class_definition(&mut self) -> Result<(), Error> {
+        let (Token::Class(name), _) = self.next()? else { unreachable!("class definitions start with a class name") };
+        self.expect(Token::Symbol('='))?;
+        let item = self.glyph_or_class()?;
+        if !item.class {
+            return Err(item.position.error("expected a class in brackets, or a class name"));
+        }
+        self.expect(Token::Symbol(';'))?;
+        self.classes.insert(name, item.glyphs);
+        Ok(())
+    }
+
+    /// Parse a glyph, a named class, or a class in brackets.
+    fn glyph_or_class(&mut self) -> Result<Item, Error> {
+        let (token, position) = self.next()?;
+        let (glyphs, class) = match token {
+            Token::Name(name) | Token::Glyph(name) => (vec![Glyph { name, position }], false),
+            Token::Class(name) => (self.named_class(&name, position)?, true),
+            Token::Symbol('[') => {
+                let mut glyphs = Vec::new();
+                loop {
+                    match self.next()? {
+                        (Token::Symbol(']'), _) => break,
+                        (Token::Name(name) | Token::Glyph(name), position) => glyphs.push(Glyph { name, position }),
+                        (Token::Class(name), position) => glyphs.extend(self.named_class(&name, position)?),
+                        (found, position) => return Err(position.error(format!("expected a glyph, found {found}"))),
+                    }
+                }
+                if glyphs.is_empty() {
+                    return Err(position.error("empty glyph class"));
+                }
+                (glyphs, true)
+            }
+            found => return Err(position.error(format!("expected a glyph or class, found {found}"))),
+        };
+        Ok(Item { glyphs, class, position, marked: false, lookups: Vec::new() })
+    }
+
+    fn named_class(&self, name: &str, position: Position) -> Result<Vec<Glyph>, Error> {
+        self.classes.get(name).cloned()
+            .ok_or_else(|| position.error(format!("no class is named `@{name}`")))
+    }
+
+    fn lookup_flags(&mut self) -> Result<LookupFlags, Error> {
+        self.next()?;
+        let mut flags = LookupFlags::empty();
+        loop {
+            match self.next()? {
+                (Token::Symbol(';'), _) => return Ok(flags),
+                (Token::Number(number), position) => {
+                    flags = u16::try_from(number).ok()
+                        .and_then(LookupFlags::from_bits)
+                        .ok_or_else(|| position.error(format!("lookup flags {number} are not supported")))?;
+                }
+                (Token::Name(name), position) => {
+                    flags |= match name.as_str() {
+                        "RightToLeft" => LookupFlags::RIGHT_TO_LEFT,
+                        "IgnoreBaseGlyphs" => LookupFlags::IGNORE_BASE_GLYPHS,
+                        "IgnoreLigatures" => LookupFlags::IGNORE_LIGATURES,
+                        "IgnoreMarks" => LookupFlags::IGNORE_MARKS,
+                        "MarkAttachmentType" | "UseMarkFilteringSet" =>
+                            return Err(position.error(format!("`{name}` is not supported"))),
+                        _ => return Err(position.error(format!("unknown lookup flag `{name}`"))),
+                    };
+                }
+                (found, position) => return Err(position.error(format!("expected a lookup flag, found {found}"))),
+            }
+        }
+    }
+
+    /// Parse the block of the lookup `name`, after its name, and return
+    /// its index.
+    fn lookup_block(&mut self, name: String, position: Position, flags: LookupFlags) -> Result<usize, Error> {
+        if self.lookup_names.contains_key(&name) {
+            return Err(position.error(format!("more than one lookup is named `{name}`")));
+        }
+        self.eat_keyword("useExtension");
+        self.expect(Token::Symbol('{'))?;
+        let mut lookup = Lookup { flags, rules: Vec::new() };
+        loop {
+            match self.peek() {
+                Some(Token::Symbol('}')) => break,
+                Some(Token::Class(_)) => self.class_definition()?,
+                Some(token) if token.is_keyword("lookupflag") => lookup.flags = self.lookup_flags()?,
+                Some(token) if is_rule(token) => {
+                    let (rule, position) = self.rule()?;
+                    if let Some((first, _)) = lookup.rules.first() {
+                        if first.kind() != rule.kind() {
+                            return Err(position.error(format!(
+                                "{} rules can't share a lookup with {} rules",
+                                rule.kind(), first.kind(),
+                            )));
+                        }
+                    }
+                    lookup.rules.push((rule, position));
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+        self.close_block(&name)?;
+        if lookup.rules.is_empty() {
+            return Err(position.error(format!("lookup `{name}` has no rules")));
+        }
+        self.file.lookups.push(lookup);
+        let idx = self.file.lookups.len() - 1;
+        self.lookup_names.insert(name, idx);
+        Ok(idx)
+    }
+
+    fn feature_block(&mut self) -> Result<(), Error> {
+        self.next()?;
+        let (name, position) = self.name()?;
+        let tag = to_tag(&name).map_err(|err| position.error(err.to_string()))?;
+        self.eat_keyword("useExtension");
+        self.expect(Token::Symbol('{'))?;
+
+        // Rules before any `script` statement apply to every language
+        // system given, or every one in the font if none were given.
+        let mut targets: Vec<Target> = if self.file.language_systems.is_empty() {
+            vec![Target::All]
+        } else {
+            self.file.language_systems.iter()
+                .map(|&(script, language)| Target::LangSys(script, Some(language).filter(|&l| l != DEFAULT_LANGUAGE)))
+                .collect()
+        };
+        let mut feature = Feature { tag, lookups: Vec::new(), defaults: Vec::new() };
+        // Whether no `script` or `language` has been given yet.
+        let mut before_scripts = true;
+        let mut script = *b"DFLT";
+        let mut flags = LookupFlags::empty();
+        // The lookup which consecutive rules are added to.
+        let mut current: Option<usize> = None;
+        loop {
+            match self.peek() {
+                Some(Token::Symbol('}')) => break,
+                Some(Token::Class(_)) => self.class_definition()?,
+                Some(token) if token.is_keyword("script") => {
+                    self.next()?;
+                    script = self.tag()?;
+                    self.expect(Token::Symbol(';'))?;
+                    targets = vec![Target::LangSys(script, None)];
+                    before_scripts = false;
+                    current = None;
+                }
+                Some(token) if token.is_keyword("language") => {
+                    self.next()?;
+                    let language = self.tag()?;
+                    let exclude_position = self.position();
+                    let exclude = self.eat_keyword("exclude_dflt");
+                    if !exclude { self.eat_keyword("include_dflt"); }
+                    if self.peek().is_some_and(|token| token.is_keyword("required")) {
+                        return Err(self.unexpected());
+                    }
+                    self.expect(Token::Symbol(';'))?;
+                    let target = match language {
+                        DEFAULT_LANGUAGE => Target::LangSys(script, None),
+                        language => Target::LangSys(script, Some(language)),
+                    };
+                    if exclude {
+                        if feature.lookups.iter().any(|&(target, _)| target == Target::All) {
+                            return Err(exclude_position.error("`exclude_dflt` needs the language systems to be given with `languagesystem`"));
+                        }
+                        let excluded: Vec<_> = feature.defaults.iter().copied()
+                            .filter(|&(other, _)| other == target)
+                            .collect();
+                        feature.lookups.retain(|entry| !excluded.contains(entry));
+                        feature.defaults.retain(|entry| !excluded.contains(entry));
+                    } else {
+                        // The language also applies its script's default lookups.
+                        let defaults: Vec<usize> = feature.lookups.iter()
+                            .filter(|&&(other, _)| other == Target::LangSys(script, None))
+                            .map(|&(_, lookup)| lookup)
+                            .collect();
+                        for lookup in defaults {
+                            feature.register_default(&[target], lookup);
+                        }
+                    }
+                    targets = vec![target];
+                    before_scripts = false;
+                    current = None;
+                }
+                Some(token) if token.is_keyword("lookupflag") => {
+                    flags = self.lookup_flags()?;
+                    current = None;
+                }
+                Some(token) if token.is_keyword("lookup") => {
+                    self.next()?;
+                    let (name, position) = self.name()?;
+                    let lookup = if self.eat(&Token::Symbol(';')) {
+                        *self.lookup_names.get(&name)
+                            .ok_or_else(|| position.error(format!("no lookup is named `{name}`")))?
+                    } else {
+                        self.lookup_block(name, position, flags)?
+                    };
+                    feature.register_as(&targets, lookup, before_scripts);
+                    current = None;
+                }
+                Some(token) if is_rule(token) => {
+                    let (rule, position) = self.rule()?;
+                    match current {
+                        Some(idx) if self.file.lookups[idx].kind() == rule.kind() =>
+                            self.file.lookups[idx].rules.push((rule, position)),
+                        _ => {
+                            self.file.lookups.push(Lookup { flags, rules: vec![(rule, position)] });
+                            let idx = self.file.lookups.len() - 1;
+                            feature.register_as(&targets, idx, before_scripts);
+                            current = Some(idx);
+                        }
+                    }
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+        self.close_block(&name)?;
+        self.file.features.push(feature);
+        Ok(())
+    }
+
+    fn rule(&mut self) -> Result<(Rule, Position), Error> {
+        let (keyword, position) = self.name()?;
+        let rule = match keyword.as_str() {
+            "sub" | "substitute" => self.substitution(position, false)?,
+            "pos" | "position" => self.positioning(position, false)?,
+            "enum" | "enumerate" => {
+                let (keyword, position) = self.name()?;
+                if !matches!(keyword.as_str(), "pos" | "position") {
+                    return Err(position.error(format!("expected `pos`, found `{keyword}`")));
+                }
+                self.positioning(position, true)?
+            }
+            "ignore" => {
+                let (keyword, position) = self.name()?;
+                match keyword.as_str() {
+                    "sub" | "substitute" => self.substitution(position, true)?,
+                    "pos" | "position" => return Err(position.error("contextual positioning is not supported")),
+                    _ => return Err(position.error(format!("expected `sub`, found `{keyword}`"))),
+                }
+            }
+            _ => unreachable!("rules start with a rule keyword"),
+        };
+        Ok((rule, position))
+    }
+
+    /// Parse the glyphs and classes a rule matches, and the lookups
+    /// given for each.
+    fn pattern(&mut self) -> Result<Vec<Item>, Error> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Number(_)) => break,
+                Some(Token::Symbol(symbol)) if *symbol != '[' => break,
+                Some(token) if token.is_keyword("by") || token.is_keyword("from") => break,
+                _ => {}
+            }
+            let mut item = self.glyph_or_class()?;
+            item.marked = self.eat(&Token::Symbol('\''));
+            while self.peek().is_some_and(|token| token.is_keyword("lookup")) {
+                let (_, position) = self.next()?;
+                if !item.marked {
+                    return Err(position.error("lookups can only be applied to marked glyphs"));
+                }
+                item.lookups.push(self.name()?);
+            }
+            items.push(item);
+        }
+        Ok(items)
+    }
+
+    fn substitution(&mut self, position: Position, ignore: bool) -> Result<Rule, Error> {
+        let items = self.pattern()?;
+        if items.is_empty() {
+            return Err(self.position().error("expected glyphs to substitute"));
+        }
+        let by_position = self.position();
+        let replacement = if self.eat_keyword("by") {
+            let replacement = self.pattern()?;
+            if replacement.is_empty() {
+                return Err(self.position().error("expected glyphs to substitute with"));
+            }
+            if let Some(item) = replacement.iter().find(|item| item.marked) {
+                return Err(item.position.error("replacement glyphs can't be marked"));
+            }
+            if let Some(item) = replacement.iter().find(|item| item.glyphs.iter().any(|glyph| glyph.name == "NULL")) {
+                return Err(item.position.error("removing glyphs is not supported"));
+            }
+            Some(replacement)
+        } else {
+            None
+        };
+        let alternates = if replacement.is_none() && self.eat_keyword("from") {
+            Some(self.glyph_or_class()?)
+        } else {
+            None
+        };
+        let end = self.expect(Token::Symbol(';'))?;
+
+        let Some(first) = items.iter().position(|item| item.marked) else {
+            if ignore {
+                return Err(position.error("`ignore` rules need marked input glyphs"));
+            }
+            if let Some(alternates) = alternates {
+                return match <[Item; 1]>::try_from(items) {
+                    Ok([item]) if item.glyphs.len() == 1 =>
+                        Ok(Rule::Alternate(item.glyphs.into_iter().next().unwrap(), alternates.glyphs)),
+                    _ => Err(position.error("only a single glyph can be given alternates")),
+                };
+            }
+            let replacement = replacement.ok_or_else(|| end.error("expected `by` or `from`"))?;
+            return simple_substitution(items, replacement, position);
+        };
+        let last = items.iter().rposition(|item| item.marked).unwrap();
+        if let Some(item) = items[first..last].iter().find(|item| !item.marked) {
+            return Err(item.position.error("marked glyphs must be consecutive"));
+        }
+        if alternates.is_some() {
+            return Err(by_position.error("alternates can't be given in context"));
+        }
+        let lookups: Vec<(u16, &(String, Position))> = items[first..=last].iter()
+            .zip(0..)
+            .flat_map(|(item, idx)| item.lookups.iter().map(move |lookup| (idx, lookup)))
+            .collect();
+        let action = match (ignore, replacement) {
+            (true, Some(_)) => return Err(by_position.error("`ignore` rules can't substitute glyphs")),
+            (true, None) if !lookups.is_empty() => {
+                let (_, (_, position)) = lookups[0];
+                return Err(position.error("`ignore` rules can't apply lookups"));
+            }
+            (true, None) => Action::Ignore,
+            (false, Some(_)) if !lookups.is_empty() => {
+                let (_, (_, position)) = lookups[0];
+                return Err(position.error("a rule can't both apply lookups and substitute with `by`"));
+            }
+            (false, Some(replacement)) => {
+                let input = items[first..=last].iter()
+                    .map(|item| Item { glyphs: item.glyphs.clone(), lookups: Vec::new(), ..*item })
+                    .collect();
+                Action::Inline(Box::new(simple_substitution(input, replacement, position)?))
+            }
+            (false, None) if lookups.is_empty() => return Err(end.error("expected `by`, or lookups to apply")),
+            (false, None) => Action::Lookups(lookups.into_iter().map(|(idx, (name, position))| {
+                let &lookup = self.lookup_names.get(name)
+                    .ok_or_else(|| position.error(format!("no lookup is named `{name}`")))?;
+                if self.file.lookups[lookup].kind().is_positioning() {
+                    return Err(position.error(format!("lookup `{name}` is not a substitution lookup")));
+                }
+                Ok((idx, lookup))
+            }).collect::<Result<_, Error>>()?),
+        };
+        let classes = |items: &[Item]| items.iter().map(|item| item.glyphs.clone()).collect();
+        Ok(Rule::Contextual(Context {
+            backtrack: classes(&items[..first]),
+            input: classes(&items[first..=last]),
+            lookahead: classes(&items[last + 1..]),
+            action,
+        }))
+    }
+
+    fn positioning(&mut self, position: Position, enumerate: bool) -> Result<Rule, Error> {
+        match self.peek() {
+            Some(token) if token.is_keyword("cursive") => {
+                self.next()?;
+                let item = self.glyph_or_class()?;
+                let entry = self.anchor()?;
+                let exit = self.anchor()?;
+                self.expect(Token::Symbol(';'))?;
+                return Ok(Rule::Cursive(item.glyphs, entry, exit));
+            }
+            Some(Token::Name(name)) if matches!(name.as_str(), "base" | "ligature" | "mark") =>
+                return Err(self.position().error("mark attachment is not supported")),
+            _ => {}
+        }
+        let items = self.pattern()?;
+        if let Some(item) = items.iter().find(|item| item.marked) {
+            return Err(item.position.error("contextual positioning is not supported"));
+        }
+        let value = self.value()?;
+        self.expect(Token::Symbol(';'))?;
+        let Ok([first, second]) = <[Item; 2]>::try_from(items) else {
+            return Err(position.error("only pairs of glyphs can be positioned"));
+        };
+        if (first.class || second.class) && !enumerate {
+            return Ok(Rule::ClassPair(first.glyphs, second.glyphs, value));
+        }
+        let pairs = first.glyphs.iter()
+            .flat_map(|left| second.glyphs.iter().map(move |right| (left.clone(), right.clone())))
+            .collect();
+        Ok(Rule::Pairs(pairs, value))
+    }
+
+    /// Parse a value record: either a number, or four numbers in angle
+    /// brackets. Only the horizontal advance can be adjusted.
+    fn value(&mut self) -> Result<i16, Error> {
+        let position = self.position();
+        if !self.eat(&Token::Symbol('<')) {
+            return self.number();
+        }
+        let values = [self.number()?, self.number()?, self.number()?, self.number()?];
+        self.expect(Token::Symbol('>'))?;
+        match values {
+            [0, 0, x_advance, 0] => Ok(x_advance),
+            _ => Err(position.error("only the horizontal advance can be adjusted")),
+        }
+    }
+
+    /// Parse an anchor, which may be `NULL`.
+    fn anchor(&mut self) -> Result<Option<Anchor>, Error> {
+        self.expect(Token::Symbol('<'))?;
+        let (keyword, position) = self.name()?;
+        if keyword != "anchor" {
+            return Err(position.error(format!("expected `anchor`, found `{keyword}`")));
+        }
+        let anchor = if self.eat_keyword("NULL") {
+            None
+        } else {
+            Some(Anchor { x: self.number()?, y: self.number()? })
+        };
+        self.expect(Token::Symbol('>'))?;
+        Ok(anchor)
+    }
+}
+
+fn is_rule(token: &Token) -> bool {
+    ["sub", "substitute", "pos", "position", "enum", "enumerate", "ignore"].iter()
+        .any(|keyword| token.is_keyword(keyword))
+}
+
+/// Classify a substitution without context by the lengths of its input
+/// and replacement.
+fn simple_substitution(input: Vec<Item>, replacement: Vec<Item>, position: Position) -> Result<Rule, Error> {
+    match (&input[..], &replacement[..]) {
+        ([from], [to]) => {
+            let pairs = match to.glyphs.len() {
+                1 => from.glyphs.iter().map(|glyph| (glyph.clone(), to.glyphs[0].clone())).collect(),
+                len if len == from.glyphs.len() => from.glyphs.iter().cloned().zip(to.glyphs.iter().cloned()).collect(),
+                len => return Err(to.position.error(format!(
+                    "{len} glyphs can't replace a class of {} glyphs", from.glyphs.len(),
+                ))),
+            };
+            Ok(Rule::Single(pairs))
+        }
+        ([from], sequence) => {
+            if from.glyphs.len() != 1 {
+                return Err(from.position.error("only a single glyph can be replaced by a sequence"));
+            }
+            if let Some(item) = sequence.iter().find(|item| item.glyphs.len() != 1) {
+                return Err(item.position.error("a glyph can only be replaced by a sequence of single glyphs"));
+            }
+            let sequence = sequence.iter().map(|item| item.glyphs[0].clone()).collect();
+            Ok(Rule::Multiple(from.glyphs[0].clone(), sequence))
+        }
+        (components, [ligature]) => {
+            if ligature.glyphs.len() != 1 {
+                return Err(ligature.position.error("a sequence can only be replaced by a single glyph"));
+            }
+            let components = components.iter().map(|item| item.glyphs.clone()).collect();
+            Ok(Rule::Ligature(components, ligature.glyphs[0].clone()))
+        }
+        _ => Err(position.error("a sequence of glyphs can't be replaced by another sequence")),
+    }
+}
+
+/// The substitutions of one lookup without context, resolved to glyphs.
+#[derive(Default)]
+struct Substitutions {
+    single: Vec<(GlyphId, GlyphId)>,
+    sequences: Vec<(GlyphId, Vec<GlyphId>)>,
+    ligatures: Vec<Ligature>,
+    /// The replacement of each glyph or ligature sequence seen so far.
+    seen: HashMap<Vec<GlyphId>, Vec<GlyphId>>,
+}
+
+impl Substitutions {
+    /// Add a substitution, unless it's already been given. A glyph or
+    /// sequence can't be given two different replacements.
+    fn insert(&mut self, from: Vec<GlyphId>, to: Vec<GlyphId>, glyph: &Glyph) -> Result<bool, Error> {
+        match self.seen.get(&from) {
+            Some(existing) if *existing == to => Ok(false),
+            Some(_) => Err(glyph.position.error(format!("`{}` is already substituted in this lookup", glyph.name))),
+            None => {
+                self.seen.insert(from, to);
+                Ok(true)
+            }
+        }
+    }
+
+    fn add(&mut self, rule: &Rule, glyph_map: &GlyphMap) -> Result<(), Error> {
+        match rule {
+            Rule::Single(pairs) => {
+                for (from, to) in pairs {
+                    let (from_id, to_id) = (from.resolve(glyph_map)?, to.resolve(glyph_map)?);
+                    if self.insert(vec![from_id], vec![to_id], from)? {
+                        self.single.push((from_id, to_id));
+                    }
+                }
+            }
+            Rule::Multiple(from, to) | Rule::Alternate(from, to) => {
+                let (from_id, to_ids) = (from.resolve(glyph_map)?, resolve_class(to, glyph_map)?);
+                if self.insert(vec![from_id], to_ids.clone(), from)? {
+                    self.sequences.push((from_id, to_ids));
+                }
+            }
+            Rule::Ligature(components, ligature) => {
+                let ligature_id = ligature.resolve(glyph_map)?;
+                // Every sequence of glyphs from the components' classes.
+                let mut sequences: Vec<Vec<GlyphId>> = vec![Vec::new()];
+                for class in components {
+                    let ids = resolve_class(class, glyph_map)?;
+                    sequences = sequences.into_iter()
+                        .flat_map(|sequence| ids.iter().map(move |&id| [&sequence[..], &[id]].concat()))
+                        .collect();
+                }
+                for sequence in sequences {
+                    if self.insert(sequence.clone(), vec![ligature_id], &components[0][0])? {
+                        self.ligatures.push((sequence, ligature_id));
+                    }
+                }
+            }
+            Rule::Contextual(_) | Rule::Pairs(..) | Rule::ClassPair(..) | Rule::Cursive(..) =>
+                unreachable!("contextual rules and positioning are compiled separately"),
+        }
+        Ok(())
+    }
+
+    fn subtable(self, kind: LookupKind) -> SubstSubtable {
+        match kind {
+            LookupKind::Single => SubstSubtable::single(self.single),
+            LookupKind::Multiple => SubstSubtable::multiple(self.sequences),
+            LookupKind::Alternate => SubstSubtable::alternates(self.sequences),
+            LookupKind::Ligature => SubstSubtable::ligatures(self.ligatures),
+            _ => unreachable!("{kind} is not a simple substitution"),
+        }
+    }
+}
+
+/// Compile each of a contextual lookup's rules into a subtable, adding
+/// the lookups its inline substitutions nest to `gsub`.
+fn compile_contextual(
+    lookup: &Lookup,
+    ids: &[Option<LookupId>],
+    glyph_map: &GlyphMap,
+    gsub: &mut GSubBuilder,
+) -> Result<Vec<SubstSubtable>, Error> {
+    let resolve_classes = |classes: &[Vec<Glyph>]| -> Result<Vec<Vec<GlyphId>>, Error> {
+        classes.iter().map(|class| resolve_class(class, glyph_map)).collect()
+    };
+    let mut subtables = Vec::new();
+    for (rule, _) in &lookup.rules {
+        let Rule::Contextual(context) = rule else { unreachable!("lookups hold rules of one kind") };
+        let lookups = match &context.action {
+            Action::Ignore => Vec::new(),
+            Action::Inline(rule) => {
+                let mut substitutions = Substitutions::default();
+                substitutions.add(rule, glyph_map)?;
+                let nested = gsub.flagged_lookup(lookup.flags, vec![substitutions.subtable(rule.kind())]);
+                vec![(0, nested)]
+            }
+            Action::Lookups(lookups) => lookups.iter()
+                .map(|&(idx, nested)| (idx, ids[nested].expect("lookups are compiled before the rules which apply them")))
+                .collect(),
+        };
+        subtables.push(SubstSubtable::ChainContextSubst(ChainRule {
+            backtrack: resolve_classes(&context.backtrack)?,
+            input: resolve_classes(&context.input)?,
+            lookahead: resolve_classes(&context.lookahead)?,
+            lookups,
+        }));
+    }
+    Ok(subtables)
+}
+
+/// Compile a lookup's pairs of glyphs and of classes, with the pairs of
+/// glyphs first, so they override the classes they're in. Only the first
+/// adjustment given for a pair applies.
+fn compile_pairs(lookup: &Lookup, glyph_map: &GlyphMap) -> Result<Vec<PosSubtable>, Error> {
+    let mut pairs = Vec::new();
+    let mut seen_pairs = HashSet::new();
+    let mut first = ClassNumbering::default();
+    let mut second = ClassNumbering::default();
+    let mut adjustments = Vec::new();
+    for (rule, position) in &lookup.rules {
+        match rule {
+            Rule::Pairs(glyph_pairs, value) => {
+                for (left, right) in glyph_pairs {
+                    let pair = (left.resolve(glyph_map)?, right.resolve(glyph_map)?);
+                    if seen_pairs.insert(pair) {
+                        pairs.push((pair.0, pair.1, *value));
+                    }
+                }
+            }
+            Rule::ClassPair(left, right, value) => {
+                resolve_class(left, glyph_map)?;
+                resolve_class(right, glyph_map)?;
+                let number = |numbering: &mut ClassNumbering, class: &[Glyph]| {
+                    let class: Vec<GlyphRef> = class.iter().map(Glyph::glyph_ref).collect();
+                    numbering.number(&class, glyph_map).map_err(|err| position.error(err.to_string()))
+                };
+                adjustments.push((number(&mut first, left)?, number(&mut second, right)?, *value));
+            }
+            _ => unreachable!("lookups hold rules of one kind"),
+        }
+    }
+    let mut subtables = Vec::new();
+    if !pairs.is_empty() {
+        subtables.push(PosSubtable::pairs(pairs));
+    }
+    if !adjustments.is_empty() {
+        let mut values = vec![vec![None; second.classes.len()]; first.classes.len()];
+        for (left, right, value) in adjustments {
+            values[left][right].get_or_insert(value);
+        }
+        let values = values.into_iter()
+            .map(|row| row.into_iter().map(Option::unwrap_or_default).collect())
+            .collect();
+        subtables.push(PosSubtable::ClassPairPos(PairClasses { first: first.classes, second: second.classes, values }));
+    }
+    Ok(subtables)
+}
+
+fn compile_cursive(lookup: &Lookup, glyph_map: &GlyphMap) -> Result<Vec<PosSubtable>, Error> {
+    let mut connections: Vec<CursiveConnection> = Vec::new();
+    let mut seen = HashSet::new();
+    for (rule, _) in &lookup.rules {
+        let Rule::Cursive(glyphs, entry, exit) = rule else { unreachable!("lookups hold rules of one kind") };
+        for glyph in glyphs {
+            let id = glyph.resolve(glyph_map)?;
+            if !seen.insert(id) {
+                return Err(glyph.position.error(format!("`{}` is already given anchors in this lookup", glyph.name)));
+            }
+            connections.push((id, *entry, *exit));
+        }
+    }
+    Ok(vec![PosSubtable::cursive(connections)])
+}
+
+// ===
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Font;
+    use crate::builder::fixtures::{base, BAR};
+
+    /// Build the base font, with the glyph "f_i", and the features of
+    /// `source`.
+    fn font(source: &str) -> Result<Font, Error> {
+        let mut builder = base();
+        builder.named_glyph("f_i", BAR).features(source)?;
+        builder.build()
+    }
+
+    fn error(source: &str) -> (usize, usize, String) {
+        match FeatureFile::parse(source) {
+            Err(Error::InvalidFeatures { line, column, message }) => (line, column, message),
+            Err(err) => panic!("unexpected error: {err}"),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn classifies_rules() {
+        let file = FeatureFile::parse("
+            @lower = [a b];
+            @upper = [A B];
+            feature test {
+                sub @lower by @upper;
+                sub f i by f_i;
+                sub a from [a.alt1 a.alt2];
+                sub x' y by z;
+                pos A V -1;
+                pos @upper @lower <0 0 -2 0>;
+            } test;
+        ").unwrap();
+        let kinds: Vec<Vec<LookupKind>> = file.lookups.iter()
+            .map(|lookup| lookup.rules.iter().map(|(rule, _)| rule.kind()).collect())
+            .collect();
+        assert_eq!(kinds, [
+            vec![LookupKind::Single],
+            vec![LookupKind::Ligature],
+            vec![LookupKind::Alternate],
+            vec![LookupKind::Contextual],
+            vec![LookupKind::Pair, LookupKind::Pair],
+        ]);
+        let [feature] = &file.features[..] else { panic!("expected one feature") };
+        assert_eq!(feature.lookups, (0..5).map(|idx| (Target::All, idx)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn registers_languages_with_their_script_defaults() {
+        let file = FeatureFile::parse("
+            languagesystem DFLT dflt;
+            languagesystem latn dflt;
+            feature locl {
+                script latn;
+                sub a by b;
+                language TRK;
+                sub c by d;
+                language DEU exclude_dflt;
+                sub e by f;
+            } locl;
+        ").unwrap();
+        let latn = |language: Option<&[u8; 4]>| Target::LangSys(*b"latn", language.copied());
+        assert_eq!(file.features[0].lookups, [
+            (latn(None), 0),
+            (latn(Some(b"TRK ")), 0),
+            (latn(Some(b"TRK ")), 1),
+            (latn(Some(b"DEU ")), 2),
+        ]);
+    }
+
+    #[test]
+    fn excludes_only_the_default_lookups() {
+        let file = FeatureFile::parse("
+            languagesystem latn dflt;
+            languagesystem latn DEU;
+            feature locl {
+                sub a by b;
+                script latn;
+                sub c by d;
+                language DEU;
+                sub e by f;
+                language DEU exclude_dflt;
+                sub g by h;
+            } locl;
+        ").unwrap();
+        let latn = |language: Option<&[u8; 4]>| Target::LangSys(*b"latn", language.copied());
+        assert_eq!(file.features[0].lookups, [
+            (latn(None), 0),
+            (latn(None), 1),
+            (latn(Some(b"DEU ")), 2),
+            (latn(Some(b"DEU ")), 3),
+        ]);
+    }
+
+    #[test]
+    fn reports_line_and_column() {
+        assert_eq!(error("feature liga {\n  sub f i by f_i\n} liga;"), (3, 1, "expected `;`, found `}`".to_string()));
+        assert_eq!(error("@a = [a b];\nsub @b by c;"), (2, 1, "unexpected `sub`".to_string()));
+        assert_eq!(error("feature liga {\n  sub f i by @b;\n} liga;"), (2, 14, "no class is named `@b`".to_string()));
+        assert_eq!(error("lookup A {\n  sub a by b;\n  pos a b -1;\n} A;"),
+            (3, 3, "pair positioning rules can't share a lookup with single substitution rules".to_string()));
+        assert_eq!(error("feature kern {\n  pos a b' -1;\n} kern;"), (2, 9, "contextual positioning is not supported".to_string()));
+        assert_eq!(error("feature liga {\n  sub a b by c d;\n} liga;"),
+            (2, 3, "a sequence of glyphs can't be replaced by another sequence".to_string()));
+        assert_eq!(error("markClass acute <anchor 0 0> @TOP;"), (1, 1, "`markClass` is not supported".to_string()));
+        assert_eq!(error("feature liga {\n  sub a ~ b;"), (2, 9, "unexpected character '~'".to_string()));
+    }
+
+    #[test]
+    fn compiles_lookups_and_features_into_the_font() {
+        let font = font("
+            languagesystem DFLT dflt;
+            languagesystem latn dflt;
+            languagesystem latn TRK;
+            lookup RAISE {
+                sub a by b;
+            } RAISE;
+            feature liga {
+                sub f i by f_i;
+            } liga;
+            feature calt {
+                sub a' b by c;
+                sub a' lookup RAISE c;
+            } calt;
+            feature locl {
+                script latn;
+                language TRK;
+                sub i by f;
+            } locl;
+            feature kern {
+                pos a b -1;
+                pos [a b] [b c] -2;
+            } kern;
+        ").unwrap();
+        let names = font.glyph_order();
+        let glyph = |name: &str| GlyphId(names.iter().position(|other| other == name).unwrap() as u16);
+        let gsub = font.gsub.as_ref().unwrap();
+        let gpos = font.gpos.as_ref().unwrap();
+
+        let [liga] = gsub.feature_lookups((*b"DFLT", None), *b"liga").unwrap()[..] else { panic!("expected one liga lookup") };
+        let [subtable] = gsub.lookup(liga).1 else { panic!("expected one subtable") };
+        assert_eq!(subtable.lookup_type(), 4);
+        // Rules before any `script` apply in every language system.
+        assert_eq!(gsub.feature_lookups((*b"latn", Some(*b"TRK ")), *b"liga"), Some(vec![liga]));
+
+        // The inline substitution gets a lookup of its own, and the
+        // other rule applies the named one.
+        let [calt] = gsub.feature_lookups((*b"DFLT", None), *b"calt").unwrap()[..] else { panic!("expected one calt lookup") };
+        let singles: Vec<(LookupId, &[(GlyphId, GlyphId)])> = gsub.lookup(calt).1.iter().map(|subtable| {
+            let SubstSubtable::ChainContextSubst(rule) = subtable else { panic!("expected contextual rules") };
+            let [(0, nested)] = rule.lookups[..] else { panic!("expected one nested lookup") };
+            let [SubstSubtable::SingleSubst(subs)] = gsub.lookup(nested).1 else { panic!("expected single substitutions") };
+            (nested, &subs[..])
+        }).collect();
+        let [(inline, inline_subs), (raise, raise_subs)] = singles[..] else { panic!("expected two rules") };
+        assert_ne!(inline, raise);
+        assert_eq!(inline_subs, [(glyph("a"), glyph("c"))]);
+        assert_eq!(raise_subs, [(glyph("a"), glyph("b"))]);
+
+        // Languages only get the lookups registered for them.
+        assert_eq!(gsub.feature_lookups((*b"latn", None), *b"locl"), None);
+        let [locl] = gsub.feature_lookups((*b"latn", Some(*b"TRK ")), *b"locl").unwrap()[..] else { panic!("expected one locl lookup") };
+        assert_eq!(gsub.lookup(locl).1.first().map(LookupSubtable::lookup_type), Some(1));
+
+        // Glyph pairs come first, so they override the class pairs.
+        let [kern] = gpos.feature_lookups((*b"DFLT", None), *b"kern").unwrap()[..] else { panic!("expected one kern lookup") };
+        let [PosSubtable::PairPos(pairs), PosSubtable::ClassPairPos(classes)] = gpos.lookup(kern).1 else {
+            panic!("expected glyph pairs, then class pairs")
+        };
+        assert_eq!(pairs, &[(glyph("a"), glyph("b"), -1)]);
+        assert_eq!(classes.first, [vec![glyph("a"), glyph("b")]]);
+        assert_eq!(classes.second, [vec![glyph("b"), glyph("c")]]);
+        assert_eq!(classes.values, [[-2]]);
+    }
+
+    #[test]
+    fn reports_where_glyphs_are_missing() {
+        let position = |source: &str| match font(source) {
+            Err(Error::InvalidFeatures { line, column, .. }) => (line, column),
+            Err(err) => panic!("unexpected error: {err}"),
+            Ok(_) => panic!("expected an error"),
+        };
+        assert_eq!(position("feature liga {\n  sub f i by f_j;\n} liga;"), (2, 14));
+        assert_eq!(position("@x = [a x];\nfeature kern {\n  pos @x b -1;\n} kern;"), (1, 9));
+        assert_eq!(position("feature calt {\n  sub a' z by c;\n} calt;"), (2, 10));
+    }
+}
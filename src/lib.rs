@@ -1,10 +1,14 @@
 mod bsearch;
+mod builder;
+mod error;
+mod fea;
 mod itertools;
 mod platform;
 mod sprite;
 mod subtable;
 mod tables;
 mod time;
+mod unicode;
 mod writeutils;
 
 use std::path::Path;
@@ -12,11 +16,14 @@ use std::fs::File;
 use std::io::{self, Seek, Write};
 use byteorder::{BigEndian, WriteBytesExt};
 use bsearch::BSearch;
-use crate::sprite::Sprite;
-use crate::tables::{CMap, Glyf, GSub, Head, HHea, HMtx, Loca, MaxP, Name, Os2, Post, name};
+use crate::tables::{CMap, GDef, Glyf, GPos, GSub, Head, HHea, HMtx, Kern, Loca, MaxP, Name, Os2, Post};
 use crate::writeutils::{TableWriter, TwoWrite};
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub use crate::builder::{Anchor, AutoKerning, ContextualRule, Figures, FontBuilder, GlyphRef, JoiningForm, MarkPosition, Spacing};
+pub use crate::error::Error;
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct GlyphId(u16);
 
 const RECORD_SIZE: u16 = 16;
@@ -32,11 +39,14 @@ const RECORD_SIZE: u16 = 16;
 // https://developer.apple.com/fonts/TrueType-Reference-Manual/RM06/Chap6.html
 pub struct Font {
     cmap: CMap, // character to glyph mapping
+    gdef: GDef, // glyph classes
     glyf: Glyf, // glyph data
-    gsub: Option<GSub>, // ligature data
+    gpos: Option<GPos>, // glyph positioning
+    gsub: Option<GSub>, // glyph substitution
     head: Head, // font header
     hhea: HHea, // horizontal header
     hmtx: HMtx, // horizontal metrics
+    kern: Option<Kern>, // legacy kerning
     loca: Loca, // index to location
     maxp: MaxP, // maximum profile
     name: Name, // naming
@@ -45,6 +55,13 @@ pub struct Font {
 }
 
 impl Font {
+    /// The name of each glyph in the font, in glyph order.
+    /// This can be passed to `FontBuilder::glyph_order` to keep glyph
+    /// IDs stable as glyphs are added to the font.
+    pub fn glyph_order(&self) -> Vec<String> {
+        self.post.glyph_names()
+    }
+
     /// Save the font to a file at the path specified.
     pub fn save<Q: AsRef<Path>>(&self, path: Q) -> io::Result<()> {
         let mut file = File::create(path)?;
@@ -57,7 +74,10 @@ impl Font {
         // TODO: I don't know what the best way to represent these tables is,
         // but hardcoding the length like this is almost surely Not It,
         // especially now that it's starting to be conditional.
-        let table_count = 10 + self.gsub.is_some() as u16;
+        let table_count = 11
+            + self.gpos.is_some() as u16
+            + self.gsub.is_some() as u16
+            + self.kern.is_some() as u16;
         let bsearch = BSearch::from(table_count, RECORD_SIZE);
         let table_ptr = 12 + (table_count * RECORD_SIZE) as u64;
         let mut writer = TwoWrite::split_at(writer, table_ptr);
@@ -71,6 +91,10 @@ impl Font {
 
         writer.swap()?;
         // Table Records
+        self.write_table(&mut writer, &self.gdef)?;
+        if let Some(gpos) = &self.gpos {
+            self.write_table(&mut writer, gpos)?;
+        }
         if let Some(gsub) = &self.gsub {
             self.write_table(&mut writer, gsub)?;
         }
@@ -80,6 +104,9 @@ impl Font {
         self.write_table(&mut writer, &self.head)?;
         self.write_table(&mut writer, &self.hhea)?;
         self.write_table(&mut writer, &self.hmtx)?;
+        if let Some(kern) = &self.kern {
+            self.write_table(&mut writer, kern)?;
+        }
         self.write_table(&mut writer, &self.loca)?;
         self.write_table(&mut writer, &self.maxp)?;
         self.write_table(&mut writer, &self.name)?;
@@ -124,6 +151,25 @@ impl Rect {
         writer.write_i16::<BigEndian>(self.y_max)?;
         Ok(())
     }
+
+    /// The smallest `Rect` containing both `self` and `other`.
+    pub(crate) fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            x_min: self.x_min.min(other.x_min),
+            y_min: self.y_min.min(other.y_min),
+            x_max: self.x_max.max(other.x_max),
+            y_max: self.y_max.max(other.y_max),
+        }
+    }
+
+    pub(crate) fn translate(&self, dx: i16, dy: i16) -> Rect {
+        Rect {
+            x_min: self.x_min + dx,
+            y_min: self.y_min + dy,
+            x_max: self.x_max + dx,
+            y_max: self.y_max + dy,
+        }
+    }
 }
 
 // Bit-aligned bitmap data padded to byte boundaries.
@@ -138,71 +184,13 @@ impl Rect {
 
 type Bitmap<'a> = &'a [u8];
 
-pub fn bitmap_font<'a, G, L>(width: usize, height: usize, missing_glyph: Bitmap<'a>, glyphs: G, ligatures: L) -> Result<Font, ()>
+pub fn bitmap_font<'a, G, L>(width: usize, height: usize, missing_glyph: Bitmap<'a>, glyphs: G, ligatures: L) -> Result<Font, Error>
 where
     G: IntoIterator<Item=(char, Bitmap<'a>)>,
     L: IntoIterator<Item=(&'a str, Bitmap<'a>)>,
 {
-    let mut glyphs: Vec<_> = glyphs.into_iter().collect();
-    glyphs.sort();
-    let (chars, glyph_bitmaps): (Vec<_>, Vec<_>) = glyphs.into_iter().unzip();
-    let (combos, ligature_bitmaps): (Vec<_>, Vec<_>) = ligatures.into_iter().unzip();
-    let ligature_offset = glyph_bitmaps.len() as u16 + 1;
-    let sprites = [missing_glyph].into_iter()
-        .chain(glyph_bitmaps)
-        .chain(ligature_bitmaps)
-        .map(|bitmap| Sprite { width, height, data: bitmap.into() });
-    let glyf = Glyf::from(sprites);
-    let loca = glyf.generate_loca();
-    let maxp = glyf.generate_maxp();
-    let cmap = CMap::from_char_order(&chars)?;
-    let mut head = Head::new();
-    head.index_to_loc_format = loca.needs_long() as i16;  // XXX: 😬
-    let mut name = Name::new();
-    // name.push(name::FONT_FAMILY, "My Neat Font");
-    // TODO: I think some or all of these are required,
-    // but this makes me sad. Revisit.
-    for id in (0..7).chain([10]) {
-        if id == 2 {
-            name.push(2, "Regular");
-        } else {
-            name.push(id, "My Neat Font");
-        }
-    }
-    let mut hhea = HHea::new();
-    let hmtx = HMtx::monospace(width as u16, vec![0; glyf.count_glyphs()]);
-    hhea.num_of_long_hor_metrics = hmtx.num_of_long_hor_metrics() as u16;
-
-    let post = Post::from_ascii_order(&chars, glyf.count_glyphs());
-
-    type Ligature = (Vec<GlyphId>, GlyphId);
-    let ligatures: Vec<Ligature> = combos.into_iter()
-        .zip(ligature_offset..)
-        .map(|(seq, idx)| {
-            let pattern: Vec<_> = seq.chars()
-                .map(|chr|
-                    chars.iter()
-                        .position(|&c| c == chr)  // XXX: probably make a lookup.
-                        .expect("ligature must be composed of glyphs that exist in the font")
-                        as u16 + 1
-                ).map(GlyphId)
-                .collect();
-            (pattern, GlyphId(idx))
-        }).collect();
-    let gsub = GSub::new(ligatures);
-
-    let font = Font {
-        cmap,
-        glyf,
-        gsub,
-        head,
-        hhea,
-        hmtx,
-        loca,
-        maxp,
-        name,
-        os2: Os2::default(),
-        post,
-    };
-    Ok(font)
+    FontBuilder::new(width, height, missing_glyph)
+        .glyphs(glyphs)
+        .ligatures(ligatures)
+        .build()
 }
@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use crate::Rect;
 
 pub struct Sprite {
     // XXX: this should be &[u8] but i dont want to live in lifetime hell right now
@@ -50,7 +51,7 @@ impl Sprite {
                 edge = [Right, Up, Left].into_iter().filter_map(|dir| {
                     let direction = edge.direction.turn(dir);
                     let candidate = Edge { x, y, direction };
-                    edges.contains(&candidate).then(|| candidate)
+                    edges.contains(&candidate).then_some(candidate)
                 }).next().expect("edges generated from pixel grid should have a looping path");
                 seen.insert(edge);
                 out.push(edge);
@@ -72,7 +73,57 @@ impl Sprite {
         }).collect()
     }
 
+    /// The bounding box of the filled pixels of this sprite, in the same
+    /// coordinate space as `find_contours`.
+    ///
+    /// Returns `None` if the sprite is blank.
+    pub fn ink_bounds(&self) -> Option<Rect> {
+        let mut bounds: Option<Rect> = None;
+        for x in 0..self.width {
+            for y in 0..self.height {
+                if !self.index((x, y)) { continue; }
+                let (x, y) = (x as i16, y as i16);
+                let pixel = Rect { x_min: x, y_min: y, x_max: x + 1, y_max: y + 1 };
+                bounds = Some(bounds.map_or(pixel, |b| b.union(&pixel)));
+            }
+        }
+        bounds
+    }
+
+    /// The horizontal extent of the filled pixels in each row of this
+    /// sprite, from the bottom row up, as `(x_min, x_max)` in the same
+    /// coordinate space as `ink_bounds`.
+    ///
+    /// Rows which are blank are `None`.
+    pub fn row_extents(&self) -> Vec<Option<(i16, i16)>> {
+        (0..self.height).map(|y| {
+            let x_min = (0..self.width).find(|&x| self.index((x, y)))?;
+            let x_max = (0..self.width).rfind(|&x| self.index((x, y)))?;
+            Some((x_min as i16, x_max as i16 + 1))
+        }).collect()
+    }
+
+    /// This sprite, flipped horizontally.
+    pub fn mirrored(&self) -> Sprite {
+        let mut data = vec![0; self.data.len()].into_boxed_slice();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.index((self.width - x - 1, y)) {
+                    let (byte, bit) = self.position((x, y));
+                    data[byte] |= bit;
+                }
+            }
+        }
+        Sprite { data, width: self.width, height: self.height }
+    }
+
     fn index(&self, (x, y): (usize, usize)) -> bool {
+        let (byte, bit) = self.position((x, y));
+        self.data[byte] & bit != 0
+    }
+
+    /// The byte holding the pixel at `(x, y)`, and the mask of its bit.
+    fn position(&self, (x, y): (usize, usize)) -> (usize, u8) {
         if x >= self.width {
             panic!("x: {x} must be less than width {}", self.width);
         }
@@ -89,10 +140,7 @@ impl Sprite {
         let y = self.height - y - 1;
 
         let idx = y * self.width + x;
-        let byte = idx / 8;
-        let offset = idx % 8;
-        let bit = 1 << offset;
-        self.data[byte] & bit != 0
+        (idx / 8, 1 << (idx % 8))
     }
 }
 
@@ -126,7 +174,7 @@ impl Edge {
     fn surrounding(x: usize, y: usize) -> [Edge; 4] {
         [
             Edge { x, y: y + 1, direction: Direction::Up },
-            Edge { x: x, y, direction: Direction::Right },
+            Edge { x, y, direction: Direction::Right },
             Edge { x: x + 1, y, direction: Direction::Down },
             Edge { x: x + 1, y: y + 1, direction: Direction::Left },
         ]
@@ -197,6 +245,44 @@ mod tests {
         contours.sort_unstable();
     }
 
+    #[test]
+    fn row_extents_of_a() {
+        let sprite = Sprite {
+            data: Box::new([
+                0b00010000,
+                0b00101000,
+                0b00101000,
+                0b01000100,
+                0b01111100,
+                0b10000010,
+                0b10000010,
+                0b00000000,
+            ]),
+            width: 8,
+            height: 8,
+        };
+        assert_eq!(sprite.row_extents(), [
+            None,
+            Some((0, 7)),
+            Some((0, 7)),
+            Some((1, 6)),
+            Some((1, 6)),
+            Some((2, 5)),
+            Some((2, 5)),
+            Some((3, 4)),
+        ]);
+    }
+
+    #[test]
+    fn mirrored_flips_columns() {
+        let sprite = Sprite {
+            data: Box::new([0b11000000, 0b00010000]),
+            width: 4,
+            height: 4,
+        };
+        assert_eq!(*sprite.mirrored().data, [0b00110000, 0b10000000]);
+    }
+
     #[test]
     fn serializes_a() {
         let a = Box::new([
@@ -228,4 +314,23 @@ mod tests {
         normalize_contours(&mut expected);
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn ink_bounds() {
+        let sprite = Sprite {
+            data: Box::new([
+                0b00000000,
+                0b00110000,
+                0b00010000,
+                0b00000000,
+            ]),
+            width: 8,
+            height: 4,
+        };
+        let expected = Rect { x_min: 2, y_min: 1, x_max: 4, y_max: 3 };
+        assert_eq!(sprite.ink_bounds(), Some(expected));
+
+        let blank = Sprite { data: Box::new([0; 4]), width: 8, height: 4 };
+        assert_eq!(blank.ink_bounds(), None);
+    }
 }
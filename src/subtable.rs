@@ -1,4 +1,5 @@
 use byteorder::{BigEndian, WriteBytesExt};
+use std::fmt;
 use std::io::{self, Cursor, Write};
 
 /// Utility buffer for font subtables.
@@ -41,11 +42,50 @@ pub(crate) struct SubtableHeader<'a>(&'a mut SubtableBuffer);
 
 impl<'a> SubtableHeader<'a> {
     pub fn mark_offset(&mut self) -> io::Result<()> {
-        let pos = self.0.body.position() as u16;
-        self.write_u16::<BigEndian>(pos + self.0.body_offset)
+        let pos = self.0.body.position() as usize + self.0.body_offset as usize;
+        self.write_u16::<BigEndian>(offset16(pos)?)
     }
 }
 
+/// Narrow a byte offset to the 16 bits most offsets are stored in,
+/// failing with an `OffsetOverflow` if it doesn't fit.
+pub(crate) fn offset16(offset: usize) -> io::Result<u16> {
+    offset.try_into().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, OffsetOverflow))
+}
+
+/// Narrow a count of entries to 16 bits, failing with an
+/// `OffsetOverflow` if it doesn't fit, so the subtable gets split.
+pub(crate) fn count16(count: usize) -> io::Result<u16> {
+    u16::try_from(count).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, OffsetOverflow))
+}
+
+/// Narrow a byte offset to 32 bits, as used by Extension subtables.
+pub(crate) fn offset32(offset: usize) -> io::Result<u32> {
+    offset.try_into().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, OffsetOverflow))
+}
+
+/// Whether a table, written to a sink, had all its offsets fit.
+pub(crate) fn fits(written: io::Result<()>) -> bool {
+    match written {
+        Ok(()) => true,
+        Err(err) if err.get_ref().is_some_and(|inner| inner.is::<OffsetOverflow>()) => false,
+        Err(err) => panic!("writing to a sink should only fail by overflowing: {err}"),
+    }
+}
+
+/// The payload of the `io::Error` returned when a table is too large
+/// for an offset into it to be written.
+#[derive(Debug)]
+pub(crate) struct OffsetOverflow;
+
+impl fmt::Display for OffsetOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("offset too large for its field")
+    }
+}
+
+impl std::error::Error for OffsetOverflow {}
+
 impl<'a> Write for SubtableHeader<'a> {
     fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
         self.0.header.write(bytes)
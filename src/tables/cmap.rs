@@ -1,6 +1,7 @@
 // https://developer.apple.com/fonts/TrueType-Reference-Manual/RM06/Chap6cmap.html
-use crate::{FontTable, TableWriter};
+use crate::{FontTable, GlyphId, TableWriter};
 use crate::bsearch::BSearch;
+use crate::error::Error;
 use std::io::{self, Write};
 use byteorder::{BigEndian, WriteBytesExt};
 use crate::platform::Platform;
@@ -11,19 +12,23 @@ pub(crate) struct CMap {
 }
 
 impl CMap {
-    pub(crate) fn from_char_order(order: &[char]) -> Result<Self, ()> {
-        let order: Result<Vec<u16>, _> = order.into_iter().map(|&c| to_u16(c)).collect();
-        let mut glyph_idx = 1;
-        let segments: Vec<_> = split_when(&order?, |&a, &b| a + 1 != b)
-            .map(|slice| {
-                let start = *slice.first().expect("`split_when` should generate non-empty slices");
-                let end = *slice.last().expect("`split_when` should generate non-empty slices");
-                let range = (end - start + 1) as i16;
-                let delta = glyph_idx - start as i16;
-                glyph_idx += range;
-                Segment { start, end, delta }
-            }).chain([Segment::end_cap()])
+    /// Build a character map from each char and the glyph it maps to.
+    pub(crate) fn from_mapping<I>(mapping: I) -> Result<Self, Error>
+    where I: IntoIterator<Item=(char, GlyphId)> {
+        let mapping: Result<Vec<(u16, u16)>, _> = mapping.into_iter()
+            .map(|(c, GlyphId(glyph))| Ok((to_u16(c)?, glyph)))
             .collect();
+        let mut mapping = mapping?;
+        mapping.sort_unstable();
+        let segments: Vec<Segment> = cheapest_segments(&mapping)
+            .into_iter()
+            .chain([Segment::end_cap()])
+            .collect();
+        // The segments are as small as they can be, so if they don't fit
+        // the subtable's 16-bit length, nothing will.
+        if format4_size(&segments) > u16::MAX as usize {
+            return Err(Error::CharMapOverflow);
+        }
 
         let record = CMapSubtableRecord {
             platform: Platform::unicode_2_0(),
@@ -34,12 +39,75 @@ impl CMap {
         };
         Ok(CMap { subtables: vec![record] })
     }
+
+    /// The glyph `chr` maps to, if any.
+    #[cfg(test)]
+    pub fn glyph(&self, chr: char) -> Option<GlyphId> {
+        let chr = to_u16(chr).ok()?;
+        let CMapSubtable::Format4 { segments, .. } = &self.subtables.first()?.subtable;
+        let segment = segments.iter().find(|segment| (segment.start..=segment.end).contains(&chr))?;
+        let glyph = match &segment.glyph_ids[..] {
+            [] => chr,
+            glyph_ids => glyph_ids[(chr - segment.start) as usize],
+        };
+        Some(GlyphId(glyph.wrapping_add(segment.delta as u16))).filter(|&GlyphId(glyph)| glyph != 0)
+    }
 }
 
-// TODO: a real-ass error type
-fn to_u16(c: char) -> Result<u16, ()> {
+/// Split the sorted (char, glyph) `mapping` into format 4 segments,
+/// minimizing the size of the subtable.
+///
+/// Runs of consecutive chars mapped to consecutive glyphs are cheapest
+/// as a single `delta` segment (8 bytes). But a sequence of many short
+/// runs, eg an alias or a gap in the middle of a range, may be cheaper
+/// as one segment which lists each glyph explicitly (8 bytes, plus 2 per
+/// char in the segment, including any unmapped chars in the gaps).
+fn cheapest_segments(mapping: &[(u16, u16)]) -> Vec<Segment> {
+    let runs: Vec<&[(u16, u16)]> = split_when(
+        mapping,
+        |&(a, glyph_a), &(b, glyph_b)| a + 1 != b || glyph_a.wrapping_add(1) != glyph_b,
+    ).collect();
+
+    // cost[j] is the cheapest encoding of runs[..j], where the last segment
+    // covers runs[start[j]..j].
+    let mut cost = vec![0_usize; runs.len() + 1];
+    let mut start = vec![0; runs.len() + 1];
+    for j in 1..=runs.len() {
+        cost[j] = cost[j - 1] + 8;
+        start[j] = j - 1;
+        let end = runs[j - 1].last().expect("`split_when` should generate non-empty slices").0;
+        for i in (0..j - 1).rev() {
+            let span = (end - runs[i][0].0) as usize + 1;
+            // `span` only grows from here, so nothing further back can win.
+            if 8 + 2 * span >= cost[j] { break; }
+            let candidate = cost[i] + 8 + 2 * span;
+            if candidate < cost[j] {
+                cost[j] = candidate;
+                start[j] = i;
+            }
+        }
+    }
+
+    let mut segments = Vec::new();
+    let mut j = runs.len();
+    while j > 0 {
+        let i = start[j];
+        segments.push(Segment::from_runs(&runs[i..j]));
+        j = i;
+    }
+    segments.reverse();
+    segments
+}
+
+/// The length in bytes of a format 4 subtable of `segments`.
+fn format4_size(segments: &[Segment]) -> usize {
+    let glyph_id_count: usize = segments.iter().map(|s| s.glyph_ids.len()).sum();
+    16 + 8 * segments.len() + 2 * glyph_id_count
+}
+
+fn to_u16(c: char) -> Result<u16, Error> {
     let full = c as u32;
-    full.try_into().map_err(|_| ())
+    full.try_into().map_err(|_| Error::UnsupportedChar(c))
 }
 
 impl FontTable for CMap {
@@ -76,10 +144,6 @@ struct CMapSubtableRecord {
 }
 
 enum CMapSubtable {
-    Format0 {
-        language_id: u16,
-        glyph_indexes: [u8; 256],
-    },
     Format4 {
         language_id: u16,
         segments: Vec<Segment>,
@@ -90,16 +154,11 @@ impl CMapSubtable {
     fn write(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
         let original_len = buf.len();
         match self {
-            CMapSubtable::Format0 { language_id, glyph_indexes } => {
-                buf.write_u16::<BigEndian>(0x0000)?;  // format
-                buf.write_u16::<BigEndian>(0x0106)?;  // subtable size
-                buf.write_u16::<BigEndian>(*language_id)?;
-                buf.extend(glyph_indexes);
-            }
             CMapSubtable::Format4 { language_id, segments } => {
                 let seg_count = segments.len() as u16;
                 let bsearch = BSearch::from(seg_count, 2);
-                let subtable_size = 16 + 8 * seg_count;
+                let subtable_size = u16::try_from(format4_size(segments))
+                    .expect("`from_mapping` should check the subtable fits");
 
                 buf.write_u16::<BigEndian>(0x0004)?;  // format
                 buf.write_u16::<BigEndian>(subtable_size)?;
@@ -118,13 +177,24 @@ impl CMapSubtable {
                 for segment in segments {
                     buf.write_i16::<BigEndian>(segment.delta)?;
                 }
-                // idRangeOffsets, but I do not understand how this could be helpful
-                // except to complicate your font parsing.
-                for _ in segments {
-                    buf.write_u16::<BigEndian>(0)?;
+                // idRangeOffsets: the byte offset from this entry to the segment's
+                // first glyph in the glyph_id_array which follows, or 0 for
+                // segments which only use their delta.
+                let mut glyph_idx = 0;
+                for (idx, segment) in segments.iter().enumerate() {
+                    if segment.glyph_ids.is_empty() {
+                        buf.write_u16::<BigEndian>(0)?;
+                    } else {
+                        let offset = 2 * (segments.len() - idx + glyph_idx);
+                        buf.write_u16::<BigEndian>(offset as u16)?;
+                        glyph_idx += segment.glyph_ids.len();
+                    }
+                }
+                for segment in segments {
+                    for &glyph in &segment.glyph_ids {
+                        buf.write_u16::<BigEndian>(glyph)?;
+                    }
                 }
-                // glyph_id_array goes here but I do not understand its purpose.
-                // we "hardcode" it to empty.
             }
         }
         Ok(buf.len() - original_len)
@@ -136,10 +206,16 @@ impl CMapSubtable {
 ///
 /// eg: a Segment over the character range 0x61..=0x7A ('a'..='z')
 /// with delta -0x60 maps these characters to glyphs 1..=26.
+///
+/// Alternatively a Segment may list the glyph for each character code in
+/// its range explicitly in `glyph_ids` (0 for unmapped codes), in which
+/// case the delta is added to each listed glyph.
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 struct Segment {
     start: u16,
     end: u16,
     delta: i16,
+    glyph_ids: Vec<u16>,
 }
 
 impl Segment {
@@ -148,6 +224,72 @@ impl Segment {
             start: 0xffff,
             end: 0xffff,
             delta: 1,
+            glyph_ids: Vec::new(),
+        }
+    }
+
+    /// A Segment covering each of the `runs` of sorted (char, glyph) pairs.
+    fn from_runs(runs: &[&[(u16, u16)]]) -> Self {
+        let &(start, glyph) = runs.first().and_then(|run| run.first())
+            .expect("segments should cover at least one char");
+        let &(end, _) = runs.last().and_then(|run| run.last())
+            .expect("segments should cover at least one char");
+        if let [_] = runs {
+            // deltas are modulo 65536
+            let delta = glyph.wrapping_sub(start) as i16;
+            return Segment { start, end, delta, glyph_ids: Vec::new() };
+        }
+        let mut glyph_ids = vec![0; (end - start) as usize + 1];
+        for &(chr, glyph) in runs.iter().copied().flatten() {
+            glyph_ids[(chr - start) as usize] = glyph;
         }
+        Segment { start, end, delta: 0, glyph_ids }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(start: u16, end: u16, delta: i16) -> Segment {
+        Segment { start, end, delta, glyph_ids: Vec::new() }
+    }
+
+    #[test]
+    fn segments_consecutive_runs() {
+        let mapping = [(0x20, 3), (0x21, 4), (0x22, 5), (0x61, 6), (0x62, 7)];
+        let expected = vec![delta(0x20, 0x22, 3 - 0x20), delta(0x61, 0x62, 6 - 0x61)];
+        assert_eq!(cheapest_segments(&mapping), expected);
+    }
+
+    #[test]
+    fn segments_interrupted_run_as_array() {
+        // 'c' aliases 'a', breaking up an otherwise consecutive run.
+        let mapping = [(0x61, 10), (0x62, 11), (0x63, 10), (0x64, 13), (0x65, 14)];
+        let expected = vec![Segment {
+            start: 0x61,
+            end: 0x65,
+            delta: 0,
+            glyph_ids: vec![10, 11, 10, 13, 14],
+        }];
+        assert_eq!(cheapest_segments(&mapping), expected);
+    }
+
+    #[test]
+    fn segments_long_runs_stay_deltas() {
+        let mut mapping: Vec<_> = (0..20).map(|c| (0x41 + c, 1 + c)).collect();
+        mapping.push((0x55, 1));
+        let expected = vec![delta(0x41, 0x54, 1 - 0x41), delta(0x55, 0x55, 1 - 0x55)];
+        assert_eq!(cheapest_segments(&mapping), expected);
+    }
+
+    #[test]
+    fn too_many_scattered_chars_overflow() {
+        // Every seventh char is its own run, 8 bytes as a delta segment
+        // or 14 as part of an array.
+        let mapping = (0..0xFFFE).step_by(7).filter_map(char::from_u32).map(|chr| (chr, GlyphId(1)));
+        assert_eq!(CMap::from_mapping(mapping).err(), Some(Error::CharMapOverflow));
+        let mapping = (0..0x8000).step_by(7).filter_map(char::from_u32).map(|chr| (chr, GlyphId(1)));
+        assert!(CMap::from_mapping(mapping).is_ok());
     }
 }
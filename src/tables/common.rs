@@ -0,0 +1,817 @@
+// Tables shared between the OpenType layout tables (GSUB, GPOS, GDEF).
+// https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2
+
+use bitflags::bitflags;
+use byteorder::{BigEndian, WriteBytesExt};
+use crate::{FontTable, GlyphId, TableWriter};
+use crate::error::Error;
+use crate::itertools::split_when;
+use crate::subtable::{self, offset16, offset32, SubtableBuffer};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
+
+/// The set of glyphs a lookup subtable applies to. Each glyph's
+/// coverage index is its position within the set, in glyph order.
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+pub(crate) enum Coverage {
+    List(Vec<GlyphId>),
+    Ranges(Vec<RangeRecord>),
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+pub(crate) struct RangeRecord {
+    start: GlyphId,
+    end: GlyphId,
+    start_coverage_index: u16,
+}
+
+impl Coverage {
+    /// Cover the given glyphs, in whichever format is smaller.
+    pub fn new<I: IntoIterator<Item=GlyphId>>(glyphs: I) -> Self {
+        let mut glyphs: Vec<GlyphId> = glyphs.into_iter().collect();
+        glyphs.sort_unstable();
+        glyphs.dedup();
+
+        let mut ranges = Vec::new();
+        let mut coverage_index = 0;
+        for run in split_when(&glyphs, |GlyphId(a), GlyphId(b)| a + 1 != *b) {
+            ranges.push(RangeRecord {
+                start: run[0],
+                end: run[run.len() - 1],
+                start_coverage_index: coverage_index,
+            });
+            coverage_index += run.len() as u16;
+        }
+
+        // 2 bytes per glyph vs 6 bytes per range, after the same 4 byte header.
+        if 6 * ranges.len() < 2 * glyphs.len() {
+            Coverage::Ranges(ranges)
+        } else {
+            Coverage::List(glyphs)
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        match self {
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#coverage-format-1
+            Coverage::List(glyph_ids) => {
+                writer.write_u16::<BigEndian>(1)?;  // coverage format
+                writer.write_u16::<BigEndian>(glyph_ids.len() as u16)?;
+                for &GlyphId(id) in glyph_ids {
+                    writer.write_u16::<BigEndian>(id)?;
+                }
+            }
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#coverage-format-2
+            Coverage::Ranges(ranges) => {
+                writer.write_u16::<BigEndian>(2)?;  // coverage format
+                writer.write_u16::<BigEndian>(ranges.len() as u16)?;
+                for range in ranges {
+                    writer.write_u16::<BigEndian>(range.start.0)?;
+                    writer.write_u16::<BigEndian>(range.end.0)?;
+                    writer.write_u16::<BigEndian>(range.start_coverage_index)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Assigns glyphs to numbered classes. Glyphs not listed are in class 0.
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+pub(crate) enum ClassDef {
+    /// The class of each glyph in turn, from `start`.
+    Array { start: GlyphId, classes: Vec<u16> },
+    Ranges(Vec<ClassRangeRecord>),
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+pub(crate) struct ClassRangeRecord {
+    start: GlyphId,
+    end: GlyphId,
+    class: u16,
+}
+
+impl ClassDef {
+    /// Assign each glyph its class, in whichever format is smaller.
+    pub fn new<I: IntoIterator<Item=(GlyphId, u16)>>(classes: I) -> Self {
+        let mut classes: Vec<(GlyphId, u16)> = classes.into_iter()
+            .filter(|&(_, class)| class != 0)
+            .collect();
+        classes.sort_unstable();
+
+        let ranges: Vec<ClassRangeRecord> = split_when(
+            &classes,
+            |&(GlyphId(a), class_a), &(GlyphId(b), class_b)| a + 1 != b || class_a != class_b,
+        ).map(|run| ClassRangeRecord {
+            start: run[0].0,
+            end: run[run.len() - 1].0,
+            class: run[0].1,
+        }).collect();
+
+        let (Some(&(start, _)), Some(&(end, _))) = (classes.first(), classes.last()) else {
+            return ClassDef::Ranges(ranges);
+        };
+        let span = (end.0 - start.0) as usize + 1;
+        // 2 bytes per glyph in the span vs 6 bytes per range, after
+        // a 6 or 4 byte header.
+        if 6 + 2 * span <= 4 + 6 * ranges.len() {
+            let mut array = vec![0; span];
+            for (GlyphId(glyph), class) in classes {
+                array[(glyph - start.0) as usize] = class;
+            }
+            ClassDef::Array { start, classes: array }
+        } else {
+            ClassDef::Ranges(ranges)
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        match self {
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#class-definition-table-format-1
+            ClassDef::Array { start, classes } => {
+                writer.write_u16::<BigEndian>(1)?;  // class format
+                writer.write_u16::<BigEndian>(start.0)?;
+                writer.write_u16::<BigEndian>(classes.len() as u16)?;
+                for &class in classes {
+                    writer.write_u16::<BigEndian>(class)?;
+                }
+            }
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#class-definition-table-format-2
+            ClassDef::Ranges(ranges) => {
+                writer.write_u16::<BigEndian>(2)?;  // class format
+                writer.write_u16::<BigEndian>(ranges.len() as u16)?;
+                for range in ranges {
+                    writer.write_u16::<BigEndian>(range.start.0)?;
+                    writer.write_u16::<BigEndian>(range.end.0)?;
+                    writer.write_u16::<BigEndian>(range.class)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The class of `glyph`: 0 if it isn't assigned one.
+    #[cfg(test)]
+    pub fn class(&self, GlyphId(glyph): GlyphId) -> u16 {
+        match self {
+            ClassDef::Array { start, classes } => glyph.checked_sub(start.0)
+                .and_then(|idx| classes.get(idx as usize).copied())
+                .unwrap_or(0),
+            ClassDef::Ranges(ranges) => ranges.iter()
+                .find(|range| (range.start.0..=range.end.0).contains(&glyph))
+                .map_or(0, |range| range.class),
+        }
+    }
+}
+
+// ===
+
+/// A table of lookups, and the features and scripts which apply them:
+/// the shared layout of GSUB and GPOS.
+pub(crate) struct LayoutTable<S> {
+    scripts: ScriptListTable,
+    features: FeatureListTable,
+    lookup: LookupListTable<S>,
+}
+
+/// Collects lookups, and the features which apply them, to build a `LayoutTable`.
+pub(crate) struct LayoutBuilder<S> {
+    lookups: Vec<LookupTable<S>>,
+    /// Each feature, and the language system it's limited to, if any.
+    features: Vec<(FeatureTable, Option<LangSysTag>)>,
+    /// Each script registered, and its languages.
+    scripts: BTreeMap<[u8; 4], BTreeSet<[u8; 4]>>,
+}
+
+/// A script tag, and a language tag within it, or `None` for the
+/// script's default language system.
+type LangSysTag = ([u8; 4], Option<[u8; 4]>);
+
+/// A lookup added to a `LayoutBuilder`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct LookupId(pub(super) u16);
+
+/// The subtables of one layout table's lookups, eg GSUB's substitutions.
+pub(crate) trait LookupSubtable: Sized {
+    /// The tag of the table the lookups are written to.
+    const TABLE_TAG: &'static [u8; 4];
+    /// The lookup type of this table's Extension lookups.
+    const EXTENSION_TYPE: u16;
+
+    fn lookup_type(&self) -> u16;
+
+    /// Split into two subtables which, tried in order, have the same
+    /// effect. Returns `None` if there's nothing left to split.
+    fn split(self) -> Option<(Self, Self)>;
+
+    fn write<W: Write>(&self, writer: W) -> io::Result<()>;
+}
+
+impl<S> Default for LayoutBuilder<S> {
+    fn default() -> Self {
+        LayoutBuilder {
+            lookups: Vec::new(),
+            features: Vec::new(),
+            scripts: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S: LookupSubtable> LayoutBuilder<S> {
+    /// Add a lookup which tries each of `subtables` in order, until one
+    /// applies. Lookups are applied in the order they're added.
+    pub fn lookup(&mut self, subtables: Vec<S>) -> LookupId {
+        self.flagged_lookup(LookupFlags::empty(), subtables)
+    }
+
+    /// Add a lookup like `lookup`, which processes glyphs as `flags` ask.
+    pub fn flagged_lookup(&mut self, flags: LookupFlags, subtables: Vec<S>) -> LookupId {
+        assert!(!subtables.is_empty(), "lookups should have at least one subtable");
+        self.lookups.push(LookupTable {
+            lookup_flag: flags,
+            subtables,
+            extension: false,
+        });
+        LookupId(self.lookups.len() as u16 - 1)
+    }
+
+    /// Register `script`, and `languages` within it. Features which
+    /// aren't limited to a language apply to all of them.
+    pub fn script<I: IntoIterator<Item=[u8; 4]>>(&mut self, script: [u8; 4], languages: I) {
+        self.scripts.entry(script).or_default().extend(languages);
+    }
+
+    /// Register the feature `tag`, which applies `lookups` when enabled.
+    pub fn feature(&mut self, tag: [u8; 4], params: Option<FeatureParams>, lookups: &[LookupId]) {
+        self.features.push((FeatureTable::new(tag, params, lookups), None));
+    }
+
+    /// Register the feature `tag` for only the `language` of `script`.
+    pub fn language_feature(&mut self, script: [u8; 4], language: [u8; 4], tag: [u8; 4], lookups: &[LookupId]) {
+        self.script(script, [language]);
+        self.features.push((FeatureTable::new(tag, None, lookups), Some((script, Some(language)))));
+    }
+
+    /// Register the feature `tag` for only the default language of `script`.
+    pub fn script_feature(&mut self, script: [u8; 4], tag: [u8; 4], lookups: &[LookupId]) {
+        self.script(script, []);
+        self.features.push((FeatureTable::new(tag, None, lookups), Some((script, None))));
+    }
+
+    /// Build the table, or `None` if no features were registered.
+    pub fn build(mut self) -> Result<Option<LayoutTable<S>>, Error> {
+        if self.features.is_empty() { return Ok(None); }
+        // The feature list, unlike the lookup list, is sorted by tag.
+        // Both it and the script list may hold the same tag more than once.
+        self.features.sort_by_key(|(feature, _)| feature.tag);
+        self.scripts.entry(*b"DFLT").or_default();
+        // Shapers only apply the first feature with each tag they find
+        // in a language system, so a language system's features with the
+        // same tag are merged into one: identified by the registered
+        // features it merges, which share a tag.
+        let merged_features = |lang_sys: LangSysTag| -> Vec<Vec<usize>> {
+            let indices: Vec<usize> = self.features.iter()
+                .enumerate()
+                .filter(|(_, (_, limit))| limit.is_none() || *limit == Some(lang_sys))
+                .map(|(idx, _)| idx)
+                .collect();
+            split_when(&indices, |&a, &b| self.features[a].0.tag != self.features[b].0.tag)
+                .map(<[usize]>::to_vec)
+                .collect()
+        };
+        let lang_sys_features: Vec<(LangSysTag, Vec<Vec<usize>>)> = self.scripts.iter()
+            .flat_map(|(&script, languages)| {
+                [None].into_iter().chain(languages.iter().copied().map(Some))
+                    .map(move |language| (script, language))
+            })
+            .map(|lang_sys| (lang_sys, merged_features(lang_sys)))
+            .collect();
+        // Sorting by the first registered feature keeps them sorted by tag.
+        let merged: BTreeSet<&Vec<usize>> = lang_sys_features.iter().flat_map(|(_, merged)| merged).collect();
+        let feature_indices: HashMap<&Vec<usize>, u16> = merged.iter().zip(0..).map(|(&indices, idx)| (indices, idx)).collect();
+        let features = merged.iter().map(|indices| {
+            let sources = indices.iter().map(|&idx| &self.features[idx].0);
+            let mut lookup_list_indices: Vec<u16> = sources.clone()
+                .flat_map(|feature| feature.lookup_list_indices.iter().copied())
+                .collect();
+            lookup_list_indices.sort_unstable();
+            lookup_list_indices.dedup();
+            let first = &self.features[indices[0]].0;
+            FeatureTable {
+                tag: first.tag,
+                params: sources.clone().find_map(|feature| feature.params.clone()),
+                lookup_list_indices,
+            }
+        }).collect();
+        let mut lang_sys_tables: BTreeMap<LangSysTag, LangSysTable> = lang_sys_features.iter()
+            .map(|(lang_sys, merged)| (*lang_sys, LangSysTable {
+                reqd_feature_idx: NO_REQUIRED_FEATURE,
+                feature_list_indices: merged.iter().map(|indices| feature_indices[indices]).collect(),
+            }))
+            .collect();
+        let scripts = self.scripts.iter().map(|(&script_tag, languages)| ScriptTable {
+            script_tag,
+            default: lang_sys_tables.remove(&(script_tag, None)),
+            lang_sys: languages.iter()
+                .filter_map(|&language| Some((language, lang_sys_tables.remove(&(script_tag, Some(language)))?)))
+                .collect(),
+        }).collect();
+        let mut table = LayoutTable {
+            scripts: ScriptListTable { scripts },
+            features: FeatureListTable { features },
+            lookup: LookupListTable { list: self.lookups },
+        };
+        table.pack()?;
+        Ok(Some(table))
+    }
+}
+
+impl<S: LookupSubtable> FontTable for LayoutTable<S> {
+    const TAG: &'static [u8; 4] = S::TABLE_TAG;
+
+    fn write<W: Write>(&self, writer: &mut TableWriter<W>) -> io::Result<()> {
+        self.write_table(writer)
+    }
+}
+
+impl<S: LookupSubtable> LayoutTable<S> {
+    fn write_table<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut subtable = SubtableBuffer::new(10);
+        {
+            let mut header = subtable.header();
+            header.write_u32::<BigEndian>(0x00010000)?;  // version
+            header.mark_offset()?;  // scripts offset
+        }
+        self.scripts.write(subtable.body())?;
+        subtable.header().mark_offset()?;  // features offset
+        self.features.write(subtable.body())?;
+        subtable.header().mark_offset()?;  // lookup offset
+        self.lookup.write(subtable.body())?;
+        subtable.write(writer)
+    }
+
+    /// Rearrange the lookups until every offset in the table fits:
+    /// first by splitting up subtables too large to address within,
+    /// then by moving all the subtables behind Extension lookups.
+    fn pack(&mut self) -> Result<(), Error> {
+        if self.fits() { return Ok(()); }
+        for lookup in &mut self.lookup.list {
+            lookup.split_subtables()?;
+        }
+        if self.fits() { return Ok(()); }
+        for lookup in &mut self.lookup.list {
+            lookup.extension = true;
+        }
+        if self.fits() { Ok(()) } else { Err(Error::OffsetOverflow) }
+    }
+
+    fn fits(&self) -> bool {
+        subtable::fits(self.write_table(io::sink()))
+    }
+}
+
+#[cfg(test)]
+impl<S> LayoutTable<S> {
+    /// Each lookup's flags and subtables, in the order they apply.
+    pub fn lookups(&self) -> impl Iterator<Item=(LookupFlags, &[S])> {
+        self.lookup.list.iter().map(|lookup| (lookup.lookup_flag, &lookup.subtables[..]))
+    }
+
+    /// The flags and subtables of the lookup `id`.
+    pub fn lookup(&self, id: LookupId) -> (LookupFlags, &[S]) {
+        let lookup = &self.lookup.list[id.0 as usize];
+        (lookup.lookup_flag, &lookup.subtables[..])
+    }
+
+    /// The lookups of the feature `tag` in the language system, or
+    /// `None` if it doesn't have the feature.
+    pub fn feature_lookups(&self, lang_sys: LangSysTag, tag: [u8; 4]) -> Option<Vec<LookupId>> {
+        let feature = self.feature_table(lang_sys, tag)?;
+        Some(feature.lookup_list_indices.iter().map(|&idx| LookupId(idx)).collect())
+    }
+
+    /// The feature `tag` in the language system as it's written, with
+    /// its params, or `None` if it doesn't have the feature.
+    pub fn feature_bytes(&self, lang_sys: LangSysTag, tag: [u8; 4]) -> Option<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.feature_table(lang_sys, tag)?.write(&mut bytes).unwrap();
+        Some(bytes)
+    }
+
+    fn feature_table(&self, (script, language): LangSysTag, tag: [u8; 4]) -> Option<&FeatureTable> {
+        let script = self.scripts.scripts.iter().find(|table| table.script_tag == script)?;
+        let lang_sys = match language {
+            None => script.default.as_ref()?,
+            Some(language) => &script.lang_sys.iter().find(|(other, _)| *other == language)?.1,
+        };
+        lang_sys.feature_list_indices.iter()
+            .map(|&idx| &self.features.features[idx as usize])
+            .find(|feature| feature.tag == tag)
+    }
+}
+
+struct LookupListTable<S> {
+    list: Vec<LookupTable<S>>,
+}
+
+impl<S: LookupSubtable> LookupListTable<S> {
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#lookup-list-table
+    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        // The subtables of Extension lookups are laid out after all the
+        // lookup tables, so they don't push later lookups out of reach
+        // of this table's 16-bit offsets.
+        let header_len = 2 + 2 * self.list.len();
+        let lookup_lens: Vec<usize> = self.list.iter()
+            .map(LookupTable::len)
+            .collect::<io::Result<_>>()?;
+        let extension_start = header_len + lookup_lens.iter().sum::<usize>();
+
+        writer.write_u16::<BigEndian>(self.list.len() as u16)?;
+        let mut lookup_start = header_len;
+        for len in &lookup_lens {
+            writer.write_u16::<BigEndian>(offset16(lookup_start)?)?;
+            lookup_start += len;
+        }
+
+        let mut extension_data = Vec::new();
+        let mut lookup_start = header_len;
+        for (lookup, len) in self.list.iter().zip(lookup_lens) {
+            if lookup.extension {
+                let mut offsets = Vec::with_capacity(lookup.subtables.len());
+                for (idx, subtable) in lookup.subtables.iter().enumerate() {
+                    let extension_pos = lookup_start + lookup.header_len() + EXTENSION_SIZE * idx;
+                    let offset = extension_start + extension_data.len() - extension_pos;
+                    offsets.push(offset32(offset)?);
+                    subtable.write(&mut extension_data)?;
+                }
+                lookup.write_extension(&mut writer, &offsets)?;
+            } else {
+                lookup.write(&mut writer)?;
+            }
+            lookup_start += len;
+        }
+        writer.write_all(&extension_data)
+    }
+}
+
+/// The size of an Extension subtable.
+const EXTENSION_SIZE: usize = 8;
+
+struct LookupTable<S> {
+    lookup_flag: LookupFlags,
+    /// Tried in order, until one applies.
+    subtables: Vec<S>,
+    /// Whether the subtables are written behind Extension subtables, which reach them with 32-bit offsets.
+    extension: bool,
+}
+
+impl<S: LookupSubtable> LookupTable<S> {
+    fn lookup_type(&self) -> u16 {
+        // The type is shared by all the lookup's subtables.
+        self.subtables.first()
+            .expect("lookups should have at least one subtable")
+            .lookup_type()
+    }
+
+    fn header_len(&self) -> usize {
+        // XXX: +2 if markFilteringSet. see below
+        6 + 2 * self.subtables.len()
+    }
+
+    /// The size of the lookup table, not counting the data of any
+    /// subtables behind extensions.
+    fn len(&self) -> io::Result<usize> {
+        if self.extension {
+            return Ok(self.header_len() + EXTENSION_SIZE * self.subtables.len());
+        }
+        let mut buf = Vec::new();
+        self.write(&mut buf)?;
+        Ok(buf.len())
+    }
+
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#lookup-table
+    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut subtable = SubtableBuffer::new(offset16(self.header_len())?);
+        self.write_header(&mut subtable.header(), self.lookup_type())?;
+        for tbl in &self.subtables {
+            subtable.header().mark_offset()?;
+            tbl.write(subtable.body())?;
+        }
+        // XXX: we'd need to write a markFilteringSet u16 here when
+        // the corresponding LookupFlag is set. Currently unsupported.
+        subtable.write(writer)
+    }
+
+    /// Write the lookup as an Extension lookup, whose subtables are
+    /// each `offsets` bytes past their Extension subtable.
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/gsub#lookuptype-7-extension-substitution
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#lookuptype-9-extension-positioning
+    fn write_extension<W: Write>(&self, writer: W, offsets: &[u32]) -> io::Result<()> {
+        let mut subtable = SubtableBuffer::new(offset16(self.header_len())?);
+        self.write_header(&mut subtable.header(), S::EXTENSION_TYPE)?;
+        for &offset in offsets {
+            subtable.header().mark_offset()?;
+            let mut body = subtable.body();
+            body.write_u16::<BigEndian>(1)?;  // format
+            body.write_u16::<BigEndian>(self.lookup_type())?;
+            body.write_u32::<BigEndian>(offset)?;
+        }
+        subtable.write(writer)
+    }
+
+    fn write_header<W: Write>(&self, mut header: W, lookup_type: u16) -> io::Result<()> {
+        header.write_u16::<BigEndian>(lookup_type)?;
+        header.write_u16::<BigEndian>(self.lookup_flag.bits)?;
+        header.write_u16::<BigEndian>(self.subtables.len() as u16)
+    }
+
+    /// Split any subtables too large to write into smaller ones,
+    /// which together have the same effect.
+    fn split_subtables(&mut self) -> Result<(), Error> {
+        let mut pending = std::mem::take(&mut self.subtables);
+        pending.reverse();
+        while let Some(candidate) = pending.pop() {
+            if subtable::fits(candidate.write(io::sink())) {
+                self.subtables.push(candidate);
+                continue;
+            }
+            let (first, second) = candidate.split().ok_or(Error::OffsetOverflow)?;
+            pending.push(second);
+            pending.push(first);
+        }
+        Ok(())
+    }
+}
+
+bitflags! {
+    pub(crate) struct LookupFlags: u16 {
+        /// For cursive attachment: the last glyph in a connected run,
+        /// rather than the first, sits on the baseline.
+        const RIGHT_TO_LEFT = 1 << 0;
+        const IGNORE_BASE_GLYPHS = 1 << 1;
+        const IGNORE_LIGATURES = 1 << 2;
+        const IGNORE_MARKS = 1 << 3;
+        // const USE_MARK_FILTERING_SET = 1 << 4;
+        // const MARK_ATTACHMENT_TYPE_MASK = 0xFF00;  // ????????
+    }
+}
+
+struct ScriptListTable {
+    scripts: Vec<ScriptTable>,
+}
+
+impl ScriptListTable {
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#slTbl_sRec
+    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let len = self.scripts.len() as u16;
+        let mut subtable = SubtableBuffer::new(2 + 6 * len);
+        subtable.header().write_u16::<BigEndian>(len)?;
+        for feature in &self.scripts {
+            subtable.header().write_all(&feature.script_tag)?;
+            subtable.header().mark_offset()?;
+            feature.write(subtable.body())?;
+        }
+        subtable.write(writer)
+    }
+}
+
+struct ScriptTable {
+    script_tag: [u8; 4],
+    default: Option<LangSysTable>,
+    lang_sys: Vec<([u8; 4], LangSysTable)>,
+}
+
+impl ScriptTable {
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#script-table-and-language-system-record
+    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let len = self.lang_sys.len() as u16;
+        let mut subtable = SubtableBuffer::new(4 + 6 * len);
+        if let Some(lang_sys) = &self.default {
+            subtable.header().mark_offset()?;
+            lang_sys.write(subtable.body())?;
+        } else {
+            subtable.header().write_u16::<BigEndian>(0)?;  // null
+        }
+        subtable.header().write_u16::<BigEndian>(len)?;
+        for (tag, ls) in &self.lang_sys {
+            subtable.header().write_all(tag)?;
+            subtable.header().mark_offset()?;
+            ls.write(subtable.body())?;
+        }
+        subtable.write(writer)
+    }
+}
+
+/// The `reqd_feature_idx` of a language system without a required feature.
+const NO_REQUIRED_FEATURE: u16 = 0xFFFF;
+
+struct LangSysTable {
+    reqd_feature_idx: u16,
+    feature_list_indices: Vec<u16>,
+}
+
+impl LangSysTable {
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#language-system-table
+    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u16::<BigEndian>(0)?;  // reserved null byte
+        writer.write_u16::<BigEndian>(self.reqd_feature_idx)?;
+        writer.write_u16::<BigEndian>(self.feature_list_indices.len() as u16)?;
+        for &idx in &self.feature_list_indices {
+            writer.write_u16::<BigEndian>(idx)?;
+        }
+        Ok(())
+    }
+}
+
+struct FeatureListTable {
+    features: Vec<FeatureTable>,
+}
+
+impl FeatureListTable {
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#flTbl
+    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let len = self.features.len() as u16;
+        let mut subtable = SubtableBuffer::new(2 + 6 * len);
+        subtable.header().write_u16::<BigEndian>(len)?;
+        for feature in &self.features {
+            subtable.header().write_all(&feature.tag)?;
+            subtable.header().mark_offset()?;
+            feature.write(subtable.body())?;
+        }
+        subtable.write(writer)
+    }
+}
+
+struct FeatureTable {
+    tag: [u8; 4],  // 4-byte feature identification tag
+    params: Option<FeatureParams>,
+    lookup_list_indices: Vec<u16>, // Array of indices into the LookupList — zero-based (first lookup is LookupListIndex = 0)
+}
+
+impl FeatureTable {
+    fn new(tag: [u8; 4], params: Option<FeatureParams>, lookups: &[LookupId]) -> Self {
+        FeatureTable {
+            tag,
+            params,
+            lookup_list_indices: lookups.iter().map(|&LookupId(idx)| idx).collect(),
+        }
+    }
+
+    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let len = self.lookup_list_indices.len() as u16;
+        let mut subtable = SubtableBuffer::new(4 + 2 * len);
+        {
+            // > Offset from start of Feature table to FeatureParams table,
+            // > if defined for the feature and present, else NULL.
+            let mut header = subtable.header();
+            match &self.params {
+                Some(_) => header.mark_offset()?,
+                None => header.write_u16::<BigEndian>(0)?,
+            }
+            header.write_u16::<BigEndian>(len)?;
+            for &idx in &self.lookup_list_indices {
+                header.write_u16::<BigEndian>(idx)?;
+            }
+        }
+        if let Some(params) = &self.params {
+            params.write(subtable.body())?;
+        }
+        subtable.write(writer)
+    }
+}
+
+/// Extra data for features which need it: so far, the names
+/// font UIs show for stylistic sets and character variants.
+// https://learn.microsoft.com/en-us/typography/opentype/spec/features_pt#ss01
+// https://learn.microsoft.com/en-us/typography/opentype/spec/features_ae#cv01-cv99
+#[derive(Clone)]
+pub(crate) enum FeatureParams {
+    StylisticSet {
+        ui_name_id: u16,
+    },
+    CharacterVariant {
+        ui_name_id: u16,
+        /// The chars whose glyphs the feature substitutes.
+        chars: Vec<char>,
+    },
+}
+
+impl FeatureParams {
+    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        match self {
+            FeatureParams::StylisticSet { ui_name_id } => {
+                writer.write_u16::<BigEndian>(0)?;  // version
+                writer.write_u16::<BigEndian>(*ui_name_id)?;
+            }
+            FeatureParams::CharacterVariant { ui_name_id, chars } => {
+                writer.write_u16::<BigEndian>(0)?;  // format
+                writer.write_u16::<BigEndian>(*ui_name_id)?;
+                writer.write_u16::<BigEndian>(0)?;  // tooltip name id
+                writer.write_u16::<BigEndian>(0)?;  // sample text name id
+                writer.write_u16::<BigEndian>(0)?;  // number of named parameters
+                writer.write_u16::<BigEndian>(0)?;  // first parameter name id
+                writer.write_u16::<BigEndian>(chars.len() as u16)?;
+                for &chr in chars {
+                    writer.write_u24::<BigEndian>(chr as u32)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tables::gsub::{GSub, GSubBuilder, Ligature, SubstSubtable};
+
+    fn glyphs(ids: &[u16]) -> Vec<GlyphId> {
+        ids.iter().copied().map(GlyphId).collect()
+    }
+
+    #[test]
+    fn coverage_scattered_glyphs_as_list() {
+        let coverage = Coverage::new(glyphs(&[9, 3, 5, 6]));
+        assert_eq!(coverage, Coverage::List(glyphs(&[3, 5, 6, 9])));
+    }
+
+    #[test]
+    fn coverage_contiguous_glyphs_as_ranges() {
+        let coverage = Coverage::new(glyphs(&[40, 41, 42, 43, 10, 11, 12, 13]));
+        assert_eq!(coverage, Coverage::Ranges(vec![
+            RangeRecord { start: GlyphId(10), end: GlyphId(13), start_coverage_index: 0 },
+            RangeRecord { start: GlyphId(40), end: GlyphId(43), start_coverage_index: 4 },
+        ]));
+    }
+
+    #[test]
+    fn class_def_dense_classes_as_array() {
+        let class_def = ClassDef::new([(GlyphId(5), 1), (GlyphId(6), 2), (GlyphId(8), 1), (GlyphId(3), 0)]);
+        assert_eq!(class_def, ClassDef::Array { start: GlyphId(5), classes: vec![1, 2, 0, 1] });
+    }
+
+    #[test]
+    fn class_def_long_runs_as_ranges() {
+        let class_def = ClassDef::new((10..40).map(|glyph| (GlyphId(glyph), 1)));
+        assert_eq!(class_def, ClassDef::Ranges(vec![
+            ClassRangeRecord { start: GlyphId(10), end: GlyphId(39), class: 1 },
+        ]));
+    }
+
+    fn ligature(pattern: &[u16], replacement: u16) -> Ligature {
+        (pattern.iter().copied().map(GlyphId).collect(), GlyphId(replacement))
+    }
+
+    fn liga(ligatures: Vec<Ligature>) -> GSub {
+        let mut gsub = GSubBuilder::default();
+        let lookup = gsub.lookup(vec![SubstSubtable::ligatures(ligatures)]);
+        gsub.feature(*b"liga", None, &[lookup]);
+        gsub.build().unwrap().unwrap()
+    }
+
+    #[test]
+    fn large_lookups_use_extensions() {
+        let ligatures = (1..=40).flat_map(|a| (1..=40).flat_map(move |b| {
+            (1..=40).map(move |c| ligature(&[a, b, c], 100))
+        })).collect();
+        let gsub = liga(ligatures);
+        let lookup = &gsub.lookup.list[0];
+        assert!(lookup.extension);
+        assert!(lookup.subtables.len() > 1);
+
+        let mut buf = Vec::new();
+        gsub.write_table(&mut buf).unwrap();
+        assert!(buf.len() > 0x10000);
+    }
+
+    #[test]
+    fn small_lookups_stay_inline() {
+        let gsub = liga(vec![ligature(&[5, 7], 10)]);
+        let lookup = &gsub.lookup.list[0];
+        assert!(!lookup.extension);
+        assert_eq!(lookup.subtables.len(), 1);
+    }
+
+    #[test]
+    fn language_features_only_in_their_language() {
+        let mut gsub = GSubBuilder::default();
+        let locl = gsub.lookup(vec![SubstSubtable::single(vec![(GlyphId(1), GlyphId(2))])]);
+        let liga = gsub.lookup(vec![SubstSubtable::ligatures(vec![ligature(&[3, 4], 5)])]);
+        gsub.language_feature(*b"latn", *b"TRK ", *b"locl", &[locl]);
+        gsub.feature(*b"liga", None, &[liga]);
+        let gsub = gsub.build().unwrap().unwrap();
+
+        let tags: Vec<_> = gsub.features.features.iter().map(|feature| &feature.tag).collect();
+        assert_eq!(tags, [b"liga", b"locl"]);
+        let scripts = &gsub.scripts.scripts;
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(&scripts[0].script_tag, b"DFLT");
+        assert_eq!(scripts[0].default.as_ref().unwrap().feature_list_indices, [0]);
+        assert_eq!(&scripts[1].script_tag, b"latn");
+        assert_eq!(scripts[1].default.as_ref().unwrap().feature_list_indices, [0]);
+        let (language, lang_sys) = &scripts[1].lang_sys[0];
+        assert_eq!(language, b"TRK ");
+        assert_eq!(lang_sys.reqd_feature_idx, NO_REQUIRED_FEATURE);
+        assert_eq!(lang_sys.feature_list_indices, [0, 1]);
+    }
+}
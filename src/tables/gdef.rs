@@ -0,0 +1,113 @@
+// https://learn.microsoft.com/en-us/typography/opentype/spec/gdef
+
+use byteorder::{BigEndian, WriteBytesExt};
+use crate::{FontTable, GlyphId, TableWriter};
+use crate::subtable::{offset16, SubtableBuffer};
+use crate::tables::common::{ClassDef, Coverage};
+use std::io::{self, Write};
+
+pub(crate) struct GDef {
+    glyph_classes: ClassDef,
+    /// Each ligature glyph, sorted, and the x positions of the carets
+    /// between its components.
+    lig_carets: Vec<(GlyphId, Vec<i16>)>,
+}
+
+/// What role a glyph plays, so lookups can skip over glyphs by role.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum GlyphClass {
+    /// A single character, spacing glyph.
+    Base = 1,
+    /// A multiple character, spacing glyph.
+    Ligature = 2,
+    /// A non-spacing combining glyph.
+    Mark = 3,
+    /// Part of a single character, spacing glyph.
+    Component = 4,
+}
+
+impl GDef {
+    pub fn new<C, L>(glyph_classes: C, lig_carets: L) -> Self
+    where
+        C: IntoIterator<Item=(GlyphId, GlyphClass)>,
+        L: IntoIterator<Item=(GlyphId, Vec<i16>)>,
+    {
+        let glyph_classes = ClassDef::new(
+            glyph_classes.into_iter().map(|(glyph, class)| (glyph, class as u16))
+        );
+        let mut lig_carets: Vec<_> = lig_carets.into_iter()
+            .filter(|(_, carets)| !carets.is_empty())
+            .collect();
+        lig_carets.sort_unstable_by_key(|&(glyph, _)| glyph);
+        lig_carets.dedup_by_key(|&mut (glyph, _)| glyph);
+        GDef { glyph_classes, lig_carets }
+    }
+
+    /// The class of `glyph`, as a number: 0 if it has none.
+    #[cfg(test)]
+    pub fn glyph_class(&self, glyph: GlyphId) -> u16 {
+        self.glyph_classes.class(glyph)
+    }
+
+    /// The carets of the ligature `glyph`, if it has any.
+    #[cfg(test)]
+    pub fn carets(&self, glyph: GlyphId) -> Option<&[i16]> {
+        self.lig_carets.iter().find(|&&(other, _)| other == glyph).map(|(_, carets)| &carets[..])
+    }
+
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/gdef#ligature-caret-list-table
+    fn write_lig_caret_list<W: Write>(&self, writer: W) -> io::Result<()> {
+        let len = self.lig_carets.len();
+        let mut subtable = SubtableBuffer::new(offset16(4 + 2 * len)?);
+        {
+            let mut header = subtable.header();
+            header.mark_offset()?;  // coverage offset
+            header.write_u16::<BigEndian>(len as u16)?;
+        }
+        Coverage::new(self.lig_carets.iter().map(|&(glyph, _)| glyph)).write(subtable.body())?;
+        for (_, carets) in &self.lig_carets {
+            subtable.header().mark_offset()?;
+            write_lig_glyph(carets, subtable.body())?;
+        }
+        subtable.write(writer)
+    }
+}
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/gdef#ligature-glyph-table
+fn write_lig_glyph<W: Write>(carets: &[i16], writer: W) -> io::Result<()> {
+    let len = carets.len();
+    let mut subtable = SubtableBuffer::new(offset16(2 + 2 * len)?);
+    subtable.header().write_u16::<BigEndian>(len as u16)?;
+    for &caret in carets {
+        subtable.header().mark_offset()?;
+        // https://learn.microsoft.com/en-us/typography/opentype/spec/gdef#caret-value-table-format-1
+        let mut body = subtable.body();
+        body.write_u16::<BigEndian>(1)?;  // format
+        body.write_i16::<BigEndian>(caret)?;  // x coordinate
+    }
+    subtable.write(writer)
+}
+
+impl FontTable for GDef {
+    const TAG: &'static [u8; 4] = b"GDEF";
+
+    fn write<W: Write>(&self, writer: &mut TableWriter<W>) -> io::Result<()> {
+        let mut subtable = SubtableBuffer::new(12);
+        {
+            let mut header = subtable.header();
+            header.write_u16::<BigEndian>(1)?;  // major version
+            header.write_u16::<BigEndian>(0)?;  // minor version
+            header.mark_offset()?;  // glyph class def offset
+        }
+        self.glyph_classes.write(subtable.body())?;
+        subtable.header().write_u16::<BigEndian>(0)?;  // attach list offset
+        if self.lig_carets.is_empty() {
+            subtable.header().write_u16::<BigEndian>(0)?;
+        } else {
+            subtable.header().mark_offset()?;  // lig caret list offset
+            self.write_lig_caret_list(subtable.body())?;
+        }
+        subtable.header().write_u16::<BigEndian>(0)?;  // mark attach class def offset
+        subtable.write(writer)
+    }
+}
@@ -3,7 +3,7 @@
 
 use byteorder::{BigEndian, WriteBytesExt};
 use bitflags::bitflags;
-use crate::{FontTable, Rect, TableWriter};
+use crate::{FontTable, GlyphId, Rect, TableWriter};
 use crate::sprite::Sprite;
 use crate::tables::{Loca, MaxP};
 use crate::itertools::split_when;
@@ -53,6 +53,16 @@ impl Glyf {
             .unwrap_or(0) as u16
     }
 
+    /// Like `max_aspect`, but sums the aspect over the components of
+    /// each compound glyph.
+    fn max_component_aspect<F: Fn(&Glyph) -> usize>(&self, f: F) -> u16 {
+        self.max_aspect(|glyph| {
+            glyph.components().iter()
+                .map(|component| f(&self.glyphs[component.glyph.0 as usize]))
+                .sum()
+        })
+    }
+
     /// The maxComponentDepth refers to the number of levels of recursion used in constructing
     /// the most complex compound glyph. The maximum legal value for maxComponentDepth is 16.
     /// If there are no components within components, all compound glyphs can be deemed simple
@@ -67,8 +77,8 @@ impl Glyf {
             num_glyphs: self.count_glyphs() as u16,
             max_points: self.max_aspect(Glyph::point_count),
             max_contours: self.max_aspect(Glyph::contour_count),
-            max_component_points: self.max_aspect(Glyph::component_point_count),
-            max_component_contours: self.max_aspect(Glyph::component_contour_count),
+            max_component_points: self.max_component_aspect(Glyph::point_count),
+            max_component_contours: self.max_component_aspect(Glyph::contour_count),
             max_zones: 2,
             max_twilight_points: 0,
             max_storage: 0,
@@ -76,7 +86,7 @@ impl Glyf {
             max_instruction_defs: 0,
             max_stack_elements: 0,
             max_size_of_instructions: self.max_aspect(Glyph::instruction_byte_count),
-            max_component_elements: 1,
+            max_component_elements: self.max_aspect(|glyph| glyph.components().len()),
             max_component_depth: self.max_component_depth(),
         }
     }
@@ -84,6 +94,12 @@ impl Glyf {
     pub fn count_glyphs(&self) -> usize {
         self.glyphs.len()
     }
+
+    /// The glyph's bounding box, if it has an outline.
+    #[cfg(test)]
+    pub fn bounds(&self, glyph: GlyphId) -> Option<Rect> {
+        self.glyphs[glyph.0 as usize].bounds()
+    }
 }
 
 pub(crate) struct Glyph {
@@ -92,10 +108,59 @@ pub(crate) struct Glyph {
 }
 
 impl Glyph {
+    /// A glyph composed of other (simple) glyphs, each shifted by some offset.
+    /// The first component is treated as the base, and provides the metrics.
+    pub fn compound(components: Vec<Component>, rect: Rect) -> Self {
+        Glyph { rect, glyph_data: GlyphData::Compound(components) }
+    }
+
+    /// A glyph with no outline, eg space.
+    /// These take up no room in the `glyf` table at all.
+    pub fn empty() -> Self {
+        let rect = Rect { x_min: 0, y_min: 0, x_max: 0, y_max: 0 };
+        Glyph { rect, glyph_data: GlyphData::Empty }
+    }
+
+    /// Shift the glyph's outline `dx` units to the right, and fit its
+    /// bounding box tightly around the outline.
+    /// Only simple glyphs have an outline to shift.
+    pub fn translate(mut self, dx: i16) -> Self {
+        let GlyphData::Simple { contours, .. } = &mut self.glyph_data else {
+            return self;
+        };
+        for coordinate in contours.iter_mut().flatten() {
+            coordinate.x += dx as i64;
+        }
+        let bound = |axis: fn(&Coordinate) -> i64, extreme: fn(i64, i64) -> i64| {
+            contours.iter().flatten().map(axis).reduce(extreme).unwrap_or(0) as i16
+        };
+        self.rect = Rect {
+            x_min: bound(|c| c.x, i64::min),
+            y_min: bound(|c| c.y, i64::min),
+            x_max: bound(|c| c.x, i64::max),
+            y_max: bound(|c| c.y, i64::max),
+        };
+        self
+    }
+
+    /// The glyph's bounding box, if it has an outline.
+    pub fn bounds(&self) -> Option<Rect> {
+        match self.glyph_data {
+            GlyphData::Empty => None,
+            _ => Some(self.rect),
+        }
+    }
+
+    /// The left edge of the glyph's bounding box.
+    pub fn x_min(&self) -> i16 {
+        self.rect.x_min
+    }
+
     fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         let mut writer = CountWriter::from(writer);
         let writer = &mut writer;
         match &self.glyph_data {
+            GlyphData::Empty => return Ok(()),
             GlyphData::Simple { instructions, contours } => {
                 let contour_count = contours.len() as u16;
                 writer.write_u16::<BigEndian>(contour_count)?;
@@ -111,7 +176,7 @@ impl Glyph {
                 // instructions
                 let instruction_len = instructions.len() as u16;
                 writer.write_u16::<BigEndian>(instruction_len)?;
-                writer.write_all(&instructions)?;
+                writer.write_all(instructions)?;
 
                 // flags + coords
                 let mut x = 0;
@@ -143,6 +208,34 @@ impl Glyph {
                 writer.write_all(&dxs.into_inner())?;
                 writer.write_all(&dys.into_inner())?;
             }
+            GlyphData::Compound(components) => {
+                writer.write_i16::<BigEndian>(-1)?;  // contour count
+                self.rect.write(writer)?;
+                for (idx, component) in components.iter().enumerate() {
+                    let mut flags = ComponentFlags::ARGS_ARE_XY_VALUES | ComponentFlags::ROUND_XY_TO_GRID;
+                    if idx == 0 {
+                        flags |= ComponentFlags::USE_MY_METRICS;
+                    }
+                    if idx + 1 < components.len() {
+                        flags |= ComponentFlags::MORE_COMPONENTS;
+                    }
+                    match (i8::try_from(component.dx), i8::try_from(component.dy)) {
+                        (Ok(dx), Ok(dy)) => {
+                            writer.write_u16::<BigEndian>(flags.bits)?;
+                            writer.write_u16::<BigEndian>(component.glyph.0)?;
+                            writer.write_i8(dx)?;
+                            writer.write_i8(dy)?;
+                        }
+                        _ => {
+                            flags |= ComponentFlags::ARG_1_AND_2_ARE_WORDS;
+                            writer.write_u16::<BigEndian>(flags.bits)?;
+                            writer.write_u16::<BigEndian>(component.glyph.0)?;
+                            writer.write_i16::<BigEndian>(component.dx)?;
+                            writer.write_i16::<BigEndian>(component.dy)?;
+                        }
+                    }
+                }
+            }
         }
         // each glyph must be u16-aligned
         if writer.count() % 2 == 1 {
@@ -168,7 +261,7 @@ impl Glyph {
                 if dx > 0 {
                     flag |= GlyphFlags::XMod;
                 }
-                dxs.write(&[byte])?;
+                dxs.write_all(&[byte])?;
             }
             Err(_) => {
                 // If the x-short Vector bit is not set, and this bit is not set,
@@ -197,7 +290,7 @@ impl Glyph {
                 if dy > 0 {
                     flag |= GlyphFlags::YMod;
                 }
-                dys.write(&[byte])?;
+                dys.write_all(&[byte])?;
             }
             Err(_) => {
                 // If the y-short Vector bit is not set, and this bit is not set,
@@ -213,6 +306,7 @@ impl Glyph {
     fn point_count(&self) -> usize {
         match &self.glyph_data {
             GlyphData::Simple { contours, .. } => contours.iter().map(|c| c.len()).sum(),
+            GlyphData::Empty | GlyphData::Compound(_) => 0,
         }
     }
 
@@ -220,26 +314,22 @@ impl Glyph {
     fn contour_count(&self) -> usize {
         match &self.glyph_data {
             GlyphData::Simple { contours, .. } => contours.len(),
+            GlyphData::Empty | GlyphData::Compound(_) => 0,
         }
     }
 
-    /// Points in compound glyph
-    fn component_point_count(&self) -> usize {
-        match &self.glyph_data {
-            GlyphData::Simple { .. } => 0,
-        }
-    }
-
-    /// Contours in compound glyph
-    fn component_contour_count(&self) -> usize {
+    /// Components of compound glyph
+    fn components(&self) -> &[Component] {
         match &self.glyph_data {
-            GlyphData::Simple { .. } => 0,
+            GlyphData::Empty | GlyphData::Simple { .. } => &[],
+            GlyphData::Compound(components) => components,
         }
     }
 
     fn instruction_byte_count(&self) -> usize {
         match &self.glyph_data {
             GlyphData::Simple { instructions, .. } => instructions.len(),
+            GlyphData::Empty | GlyphData::Compound(_) => 0,
         }
     }
 }
@@ -247,6 +337,9 @@ impl Glyph {
 impl From<Sprite> for Glyph {
     fn from(sprite: Sprite) -> Self {
         let contours = sprite.find_contours();
+        if contours.is_empty() {
+            return Glyph::empty();
+        }
         let glyph_data = GlyphData::Simple {
             instructions: Vec::new(),
             contours: into_contours(&contours),
@@ -262,8 +355,8 @@ impl From<Sprite> for Glyph {
 }
 
 fn into_contours(contours: &[Vec<(usize, usize)>]) -> Vec<Contour> {
-    contours.into_iter().map(|contour| {
-        contour.into_iter().map(|&(x, y)| Coordinate {
+    contours.iter().map(|contour| {
+        contour.iter().map(|&(x, y)| Coordinate {
             x: x.try_into().unwrap(),
             y: y.try_into().unwrap(),
             on_curve: true,
@@ -272,11 +365,18 @@ fn into_contours(contours: &[Vec<(usize, usize)>]) -> Vec<Contour> {
 }
 
 enum GlyphData {
+    Empty,
     Simple {
         instructions: Vec<u8>, // XXX: ???????
         contours: Vec<Contour>,
     },
-    // Compound(Vec<>),
+    Compound(Vec<Component>),
+}
+
+pub(crate) struct Component {
+    pub glyph: GlyphId,
+    pub dx: i16,
+    pub dy: i16,
 }
 
 type Contour = Vec<Coordinate>;
@@ -0,0 +1,345 @@
+// https://learn.microsoft.com/en-us/typography/opentype/spec/gpos
+
+use byteorder::{BigEndian, WriteBytesExt};
+use crate::{Anchor, GlyphId};
+use crate::itertools::split_when;
+use crate::subtable::{offset16, SubtableBuffer};
+use crate::tables::common::{ClassDef, Coverage, LayoutBuilder, LayoutTable, LookupSubtable};
+use std::io::{self, Write};
+
+pub(crate) type GPos = LayoutTable<PosSubtable>;
+pub(crate) type GPosBuilder = LayoutBuilder<PosSubtable>;
+
+/// Which fields a value record holds: so far, only the horizontal
+/// advance, which is all kerning needs.
+// https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#value-record
+const X_ADVANCE: u16 = 0x0004;
+
+/// A glyph in a cursive script, and the points on it where the previous
+/// glyph's exit and the next glyph's entry connect, if any.
+pub(crate) type CursiveConnection = (GlyphId, Option<Anchor>, Option<Anchor>);
+
+// Named after the lookup types in the spec.
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum PosSubtable {
+    /// Each pair of glyphs and the adjustment to the first's advance,
+    /// sorted by pair.
+    PairPos(Vec<(GlyphId, GlyphId, i16)>),
+    ClassPairPos(PairClasses),
+    /// Sorted by glyph.
+    CursivePos(Vec<CursiveConnection>),
+    MarkBasePos(MarkAttachment),
+    MarkMarkPos(MarkAttachment),
+}
+
+/// Adjusts the advance of glyphs in one class, when followed by
+/// glyphs in another.
+pub(crate) struct PairClasses {
+    /// The glyphs in each class of first glyphs. No glyph is in two classes.
+    pub first: Vec<Vec<GlyphId>>,
+    /// The glyphs in each class of second glyphs. No glyph is in two classes.
+    pub second: Vec<Vec<GlyphId>>,
+    /// The adjustment for each first class, then each second class.
+    pub values: Vec<Vec<i16>>,
+}
+
+/// Attaches marks of each class to a point on the preceding glyph: a
+/// base glyph for `MarkBasePos`, or another mark for `MarkMarkPos`.
+pub(crate) struct MarkAttachment {
+    /// Each mark, its class, and the point on it which attaches,
+    /// sorted by glyph.
+    pub marks: Vec<(GlyphId, u16, Anchor)>,
+    /// Each glyph marks attach to, and the point each class of mark
+    /// attaches to on it, if any, sorted by glyph.
+    pub bases: Vec<(GlyphId, Vec<Option<Anchor>>)>,
+}
+
+impl MarkAttachment {
+    pub fn new(mut marks: Vec<(GlyphId, u16, Anchor)>, mut bases: Vec<(GlyphId, Vec<Option<Anchor>>)>) -> Self {
+        marks.sort_unstable_by_key(|&(glyph, _, _)| glyph);
+        bases.sort_unstable_by_key(|&(glyph, _)| glyph);
+        MarkAttachment { marks, bases }
+    }
+
+    fn class_count(&self) -> usize {
+        self.marks.iter().map(|&(_, class, _)| class as usize + 1).max().unwrap_or(0)
+    }
+
+    /// Split the bases in two, each keeping all the marks.
+    fn split(mut self) -> Option<(Self, Self)> {
+        if self.bases.len() < 2 { return None; }
+        let rest = MarkAttachment {
+            marks: self.marks.clone(),
+            bases: self.bases.split_off(self.bases.len() / 2),
+        };
+        Some((self, rest))
+    }
+
+    // The two formats share a layout, with marks attaching to bases or
+    // to other marks.
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#mark-to-base-attachment-positioning-format-1-mark-to-base-attachment-point
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#mark-to-mark-attachment-positioning-format-1-mark-to-mark-attachment
+    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let class_count = self.class_count();
+        let mut subtable = SubtableBuffer::new(12);
+        {
+            let mut header = subtable.header();
+            header.write_u16::<BigEndian>(1)?;  // format
+            header.mark_offset()?;  // offset to mark coverage table
+        }
+        Coverage::new(self.marks.iter().map(|&(glyph, _, _)| glyph)).write(subtable.body())?;
+        subtable.header().mark_offset()?;  // offset to base coverage table
+        Coverage::new(self.bases.iter().map(|&(glyph, _)| glyph)).write(subtable.body())?;
+        subtable.header().write_u16::<BigEndian>(class_count as u16)?;
+
+        // https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#mark-array-table
+        subtable.header().mark_offset()?;  // offset to mark array
+        let mut mark_array = SubtableBuffer::new(offset16(2 + 4 * self.marks.len())?);
+        mark_array.header().write_u16::<BigEndian>(self.marks.len() as u16)?;
+        for &(_, class, anchor) in &self.marks {
+            mark_array.header().write_u16::<BigEndian>(class)?;
+            mark_array.header().mark_offset()?;
+            write_anchor(anchor, mark_array.body())?;
+        }
+        mark_array.write(subtable.body())?;
+
+        // https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#base-array-table
+        subtable.header().mark_offset()?;  // offset to base array
+        let mut base_array = SubtableBuffer::new(offset16(2 + 2 * class_count * self.bases.len())?);
+        base_array.header().write_u16::<BigEndian>(self.bases.len() as u16)?;
+        for (_, anchors) in &self.bases {
+            for class in 0..class_count {
+                match anchors.get(class).copied().flatten() {
+                    Some(anchor) => {
+                        base_array.header().mark_offset()?;
+                        write_anchor(anchor, base_array.body())?;
+                    }
+                    None => base_array.header().write_u16::<BigEndian>(0)?,
+                }
+            }
+        }
+        base_array.write(subtable.body())?;
+        subtable.write(writer)
+    }
+}
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#anchor-table-format-1-design-units
+fn write_anchor<W: Write>(anchor: Anchor, mut writer: W) -> io::Result<()> {
+    writer.write_u16::<BigEndian>(1)?;  // format
+    writer.write_i16::<BigEndian>(anchor.x)?;
+    writer.write_i16::<BigEndian>(anchor.y)
+}
+
+impl PosSubtable {
+    pub fn pairs(mut pairs: Vec<(GlyphId, GlyphId, i16)>) -> Self {
+        pairs.sort_unstable_by_key(|&(first, second, _)| (first, second));
+        Self::PairPos(pairs)
+    }
+
+    pub fn cursive(mut connections: Vec<CursiveConnection>) -> Self {
+        connections.sort_unstable_by_key(|&(glyph, _, _)| glyph);
+        Self::CursivePos(connections)
+    }
+}
+
+impl LookupSubtable for PosSubtable {
+    const TABLE_TAG: &'static [u8; 4] = b"GPOS";
+    const EXTENSION_TYPE: u16 = 9;
+
+    fn lookup_type(&self) -> u16 {
+        match self {
+            Self::PairPos(_) | Self::ClassPairPos(_) => 2,
+            Self::CursivePos(_) => 3,
+            Self::MarkBasePos(_) => 4,
+            Self::MarkMarkPos(_) => 6,
+        }
+    }
+
+    fn split(self) -> Option<(Self, Self)> {
+        match self {
+            Self::PairPos(mut pairs) => {
+                // Keep each first glyph's pairs together in one subtable,
+                // so it's only covered once.
+                let boundary = |&idx: &usize| pairs[idx - 1].0 != pairs[idx].0;
+                let mid = pairs.len() / 2;
+                let idx = (mid.max(1)..pairs.len()).find(boundary)
+                    .or_else(|| (1..mid).rev().find(boundary))?;
+                let rest = pairs.split_off(idx);
+                Some((Self::PairPos(pairs), Self::PairPos(rest)))
+            }
+            Self::ClassPairPos(mut classes) => {
+                if classes.first.len() < 2 { return None; }
+                let mid = classes.first.len() / 2;
+                let rest = PairClasses {
+                    first: classes.first.split_off(mid),
+                    second: classes.second.clone(),
+                    values: classes.values.split_off(mid),
+                };
+                Some((Self::ClassPairPos(classes), Self::ClassPairPos(rest)))
+            }
+            Self::CursivePos(mut connections) => {
+                if connections.len() < 2 { return None; }
+                let rest = connections.split_off(connections.len() / 2);
+                Some((Self::CursivePos(connections), Self::CursivePos(rest)))
+            }
+            Self::MarkBasePos(attachment) => {
+                let (first, rest) = attachment.split()?;
+                Some((Self::MarkBasePos(first), Self::MarkBasePos(rest)))
+            }
+            Self::MarkMarkPos(attachment) => {
+                let (first, rest) = attachment.split()?;
+                Some((Self::MarkMarkPos(first), Self::MarkMarkPos(rest)))
+            }
+        }
+    }
+
+    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        match self {
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#pair-adjustment-positioning-format-1-adjustments-for-glyph-pairs
+            Self::PairPos(pairs) => {
+                let pair_sets: Vec<_> = split_when(pairs, |a, b| a.0 != b.0).collect();
+                let mut subtable = SubtableBuffer::new(offset16(10 + 2 * pair_sets.len())?);
+                {
+                    let mut header = subtable.header();
+                    header.write_u16::<BigEndian>(1)?;  // format
+                    header.mark_offset()?;  // offset to coverage table
+                    header.write_u16::<BigEndian>(X_ADVANCE)?;  // first value format
+                    header.write_u16::<BigEndian>(0)?;  // second value format
+                    header.write_u16::<BigEndian>(pair_sets.len() as u16)?;
+                }
+                Coverage::new(pair_sets.iter().map(|set| set[0].0)).write(subtable.body())?;
+                for set in pair_sets {
+                    subtable.header().mark_offset()?;
+                    let mut body = subtable.body();
+                    body.write_u16::<BigEndian>(set.len() as u16)?;
+                    for &(_, GlyphId(second), value) in set {
+                        body.write_u16::<BigEndian>(second)?;
+                        body.write_i16::<BigEndian>(value)?;
+                    }
+                }
+                subtable.write(writer)
+            }
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#pair-adjustment-positioning-format-2-class-pair-adjustment
+            Self::ClassPairPos(classes) => {
+                // Glyphs in no second class are in class 0, which isn't
+                // adjusted. Every first glyph is covered, so the first
+                // classes can start from 0.
+                let class1_count = classes.first.len();
+                let class2_count = classes.second.len() + 1;
+                // The records come first, so if they fit, so do the counts.
+                let records_len = 2 * class1_count * class2_count;
+                let mut subtable = SubtableBuffer::new(offset16(16 + records_len)?);
+                {
+                    let mut header = subtable.header();
+                    header.write_u16::<BigEndian>(2)?;  // format
+                    header.mark_offset()?;  // offset to coverage table
+                    header.write_u16::<BigEndian>(X_ADVANCE)?;  // first value format
+                    header.write_u16::<BigEndian>(0)?;  // second value format
+                }
+                Coverage::new(classes.first.iter().flatten().copied()).write(subtable.body())?;
+                subtable.header().mark_offset()?;  // offset to first class def
+                ClassDef::new(classes.first.iter().zip(0..).flat_map(|(glyphs, class)| {
+                    glyphs.iter().map(move |&glyph| (glyph, class))
+                })).write(subtable.body())?;
+                subtable.header().mark_offset()?;  // offset to second class def
+                ClassDef::new(classes.second.iter().zip(1..).flat_map(|(glyphs, class)| {
+                    glyphs.iter().map(move |&glyph| (glyph, class))
+                })).write(subtable.body())?;
+                let mut header = subtable.header();
+                header.write_u16::<BigEndian>(class1_count as u16)?;
+                header.write_u16::<BigEndian>(class2_count as u16)?;
+                for values in &classes.values {
+                    header.write_i16::<BigEndian>(0)?;  // second class 0
+                    for &value in values {
+                        header.write_i16::<BigEndian>(value)?;
+                    }
+                }
+                subtable.write(writer)
+            }
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/gpos#cursive-attachment-positioning-format1-cursive-attachment
+            Self::CursivePos(connections) => {
+                let mut subtable = SubtableBuffer::new(offset16(6 + 4 * connections.len())?);
+                {
+                    let mut header = subtable.header();
+                    header.write_u16::<BigEndian>(1)?;  // format
+                    header.mark_offset()?;  // offset to coverage table
+                    header.write_u16::<BigEndian>(connections.len() as u16)?;
+                }
+                Coverage::new(connections.iter().map(|&(glyph, _, _)| glyph)).write(subtable.body())?;
+                for &(_, entry, exit) in connections {
+                    for anchor in [entry, exit] {
+                        match anchor {
+                            Some(anchor) => {
+                                subtable.header().mark_offset()?;
+                                write_anchor(anchor, subtable.body())?;
+                            }
+                            None => subtable.header().write_u16::<BigEndian>(0)?,
+                        }
+                    }
+                }
+                subtable.write(writer)
+            }
+            Self::MarkBasePos(attachment) | Self::MarkMarkPos(attachment) => attachment.write(writer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pair_pos_grouped_by_first_glyph() {
+        let pairs = PosSubtable::pairs(vec![
+            (GlyphId(5), GlyphId(2), -1),
+            (GlyphId(3), GlyphId(9), -2),
+            (GlyphId(3), GlyphId(4), 1),
+        ]);
+        let mut buf = Vec::new();
+        pairs.write(&mut buf).unwrap();
+        assert_eq!(buf, [
+            0, 1,  // format
+            0, 14,  // coverage offset
+            0, 4, 0, 0,  // value formats
+            0, 2, 0, 22, 0, 32,  // pair sets
+            0, 1, 0, 2, 0, 3, 0, 5,  // coverage
+            0, 2, 0, 4, 0, 1, 0, 9, 0xFF, 0xFE,  // pairs after glyph 3
+            0, 1, 0, 2, 0xFF, 0xFF,  // pairs after glyph 5
+        ]);
+    }
+
+    #[test]
+    fn cursive_pos_skips_missing_anchors() {
+        let cursive = PosSubtable::cursive(vec![
+            (GlyphId(7), Some(Anchor { x: 6, y: 0 }), None),
+            (GlyphId(4), None, Some(Anchor { x: 1, y: 0 })),
+        ]);
+        let mut buf = Vec::new();
+        cursive.write(&mut buf).unwrap();
+        assert_eq!(buf, [
+            0, 1,  // format
+            0, 14,  // coverage offset
+            0, 2,  // entry/exit count
+            0, 0, 0, 22,  // glyph 4
+            0, 28, 0, 0,  // glyph 7
+            0, 1, 0, 2, 0, 4, 0, 7,  // coverage
+            0, 1, 0, 1, 0, 0,  // glyph 4 exit
+            0, 1, 0, 6, 0, 0,  // glyph 7 entry
+        ]);
+    }
+
+    #[test]
+    fn pair_pos_split_between_first_glyphs() {
+        let pairs = PosSubtable::pairs(vec![
+            (GlyphId(3), GlyphId(4), 1),
+            (GlyphId(3), GlyphId(5), 1),
+            (GlyphId(3), GlyphId(6), 1),
+            (GlyphId(7), GlyphId(4), 1),
+        ]);
+        let Some((PosSubtable::PairPos(first), PosSubtable::PairPos(rest))) = pairs.split() else {
+            panic!("expected two pair subtables");
+        };
+        assert_eq!(first.len(), 3);
+        assert_eq!(rest.len(), 1);
+    }
+}
@@ -1,159 +1,188 @@
 // https://learn.microsoft.com/en-us/typography/opentype/spec/gsub
 
-use bitflags::bitflags;
 use byteorder::{BigEndian, WriteBytesExt};
-use crate::{FontTable, GlyphId, TableWriter};
+use crate::GlyphId;
 use crate::itertools::split_when;
-use crate::subtable::SubtableBuffer;
+use crate::subtable::{count16, offset16, SubtableBuffer};
+use crate::tables::common::{Coverage, LayoutBuilder, LayoutTable, LookupId, LookupSubtable};
+use std::cmp::Reverse;
 use std::io::{self, Write};
 
-pub(crate) struct GSub {
-    scripts: ScriptListTable,
-    features: FeatureListTable,
-    lookup: LookupListTable,
+pub(crate) type GSub = LayoutTable<SubstSubtable>;
+pub(crate) type GSubBuilder = LayoutBuilder<SubstSubtable>;
+
+// Named after the lookup types in the spec.
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum SubstSubtable {
+    /// Each glyph and its replacement, sorted by glyph.
+    SingleSubst(Vec<(GlyphId, GlyphId)>),
+    /// Each glyph and the sequence replacing it, sorted by glyph.
+    MultipleSubst(Vec<(GlyphId, Vec<GlyphId>)>),
+    /// Each glyph and its alternates to choose from, sorted by glyph.
+    AlternateSubst(Vec<(GlyphId, Vec<GlyphId>)>),
+    /// Grouped by first glyph, in ascending order. Within a group,
+    /// > Ligatures with more components must be stored ahead of those
+    /// > with fewer components in order to be found.
+    LigatureSubst(Vec<Ligature>),
+    ChainContextSubst(ChainRule),
 }
 
-impl FontTable for GSub {
-    const TAG: &'static [u8; 4] = b"GSUB";
-
-    fn write<W: Write>(&self, writer: &mut TableWriter<W>) -> io::Result<()> {
-        let mut subtable = SubtableBuffer::new(10);
-        {
-            let mut header = subtable.header();
-            header.write_u32::<BigEndian>(0x00010000)?;  // version
-            header.mark_offset()?;  // scripts offset
-        }
-        self.scripts.write(subtable.body())?;
-        subtable.header().mark_offset()?;  // features offset
-        self.features.write(subtable.body())?;
-        subtable.header().mark_offset()?;  // lookup offset
-        self.lookup.write(subtable.body())?;
-        subtable.write(writer)
-    }
+const SET_RECORD_SIZE: usize = 2;
+pub(crate) type Ligature = (Vec<GlyphId>, GlyphId);
+
+/// Applies nested lookups to a sequence of input glyphs, when it's
+/// surrounded by the given context. Each position in the sequence and
+/// its context matches any one of a set of glyphs.
+pub(crate) struct ChainRule {
+    /// In reading order, ending with the glyph just before the input.
+    pub backtrack: Vec<Vec<GlyphId>>,
+    pub input: Vec<Vec<GlyphId>>,
+    pub lookahead: Vec<Vec<GlyphId>>,
+    /// Each lookup to apply, and the index into `input` to apply it at.
+    pub lookups: Vec<(u16, LookupId)>,
 }
 
-impl GSub {
-    pub fn new(mut ligatures: Vec<Ligature>) -> Option<Self> {
-        if ligatures.is_empty() { return None; }
-        ligatures.sort_unstable();
-        let list = vec![LookupTable {
-            lookup_flag: LookupFlags::empty(),
-            subtable: LookupSubtable::LigatureSubst(ligatures),
-        }];
-        let features = vec![FeatureTable {
-            tag: *b"liga",
-            lookup_list_indices: vec![0],
-        }];
-        let scripts = vec![ScriptTable {
-            default: Some(LangSysTable {
-                reqd_feature_idx: 0,
-                feature_list_indices: Vec::new(),
-            }),
-            script_tag: *b"DFLT",
-            lang_sys: Vec::new(),
-        }];
-        Some(GSub {
-            scripts: ScriptListTable { scripts },
-            features: FeatureListTable { features },
-            lookup: LookupListTable { list },
-        })
+impl SubstSubtable {
+    pub fn single(mut substitutions: Vec<(GlyphId, GlyphId)>) -> Self {
+        substitutions.sort_unstable();
+        Self::SingleSubst(substitutions)
     }
-}
 
-struct LookupListTable {
-    list: Vec<LookupTable>
-}
-
-impl LookupListTable {
-    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#lookup-list-table
-    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
-        let len = self.list.len() as u16;
-        let mut subtable = SubtableBuffer::new(2 + len * 2);
-        subtable.header().write_u16::<BigEndian>(len)?;
-        for tbl in &self.list {
-            subtable.header().mark_offset()?;
-            tbl.write(&mut subtable.body())?;
-        }
-        subtable.write(writer)
+    pub fn multiple(mut substitutions: Vec<(GlyphId, Vec<GlyphId>)>) -> Self {
+        substitutions.sort_unstable();
+        Self::MultipleSubst(substitutions)
     }
-}
 
-struct LookupTable {
-    lookup_flag: LookupFlags,
-    subtable: LookupSubtable,
-}
-
-impl LookupTable {
-    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#lookup-table
-    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
-        // As near as i can tell, there's no meaningful distinction between
-        // the list of subtables here and the list of subtables on the next layer down.
-        // It's not like this one can be a mix of different types or anything,
-        // the type is homogeneous on _this_ level! 🙃
-        //
-        // Hardcode table len 1 here i guess!!
-        let subtable_count = 1;
-
-        // XXX: +2 if markFilteringSet. see below
-        let offset = 6 + 2 * subtable_count;
-        let mut subtable = SubtableBuffer::new(offset);
-        let lookup_type = self.subtable.lookup_type();
-        {
-            let mut header = subtable.header();
-            header.write_u16::<BigEndian>(lookup_type)?;
-            header.write_u16::<BigEndian>(self.lookup_flag.bits)?;
-            header.write_u16::<BigEndian>(subtable_count)?;
-            header.mark_offset()?;
-        }
-        self.subtable.write(subtable.body())?;
-
-        // XXX: we'd need to write a markFilteringSet u16 here when
-        // the corresponding LookupFlag is set. Currently unsupported.
-        subtable.write(writer)
+    pub fn alternates(mut substitutions: Vec<(GlyphId, Vec<GlyphId>)>) -> Self {
+        substitutions.sort_unstable_by_key(|&(glyph, _)| glyph);
+        Self::AlternateSubst(substitutions)
     }
-}
 
-bitflags! {
-    struct LookupFlags: u16 {
-        const RIGHT_TO_LEFT = 1 << 0;
-        const IGNORE_BASE_GLYPHS = 1 << 1;
-        const IGNORE_LIGATURES = 1 << 2;
-        const IGNORE_MARKS = 1 << 3;
-        // const USE_MARK_FILTERING_SET = 1 << 4;
-        // const MARK_ATTACHMENT_TYPE_MASK = 0xFF00;  // ????????
+    pub fn ligatures(mut ligatures: Vec<Ligature>) -> Self {
+        // Ligatures are grouped into sets by their first glyph, and each set
+        // is tried in order: longer ligatures must come first to be found.
+        // Otherwise keep the order they were given in.
+        ligatures.sort_by_key(|(pattern, _)| (pattern.first().copied(), Reverse(pattern.len())));
+        Self::LigatureSubst(ligatures)
     }
 }
 
-enum LookupSubtable {
-    // TODO: these gotta be sorted.
-    // how do we enforce this?
-    // > Ligatures with more components must be stored ahead of those
-    // > with fewer components in order to be found.
-    LigatureSubst(Vec<Ligature>),
-}
-
-const SET_RECORD_SIZE: u16 = 2;
-type Ligature = (Vec<GlyphId>, GlyphId);
+impl LookupSubtable for SubstSubtable {
+    const TABLE_TAG: &'static [u8; 4] = b"GSUB";
+    const EXTENSION_TYPE: u16 = 7;
 
-impl LookupSubtable {
     fn lookup_type(&self) -> u16 {
         match self {
+            Self::SingleSubst(_) => 1,
+            Self::MultipleSubst(_) => 2,
+            Self::AlternateSubst(_) => 3,
             Self::LigatureSubst(_) => 4,
+            Self::ChainContextSubst(_) => 6,
+        }
+    }
+
+    fn split(self) -> Option<(Self, Self)> {
+        match self {
+            Self::SingleSubst(mut substitutions) => {
+                if substitutions.len() < 2 { return None; }
+                let rest = substitutions.split_off(substitutions.len() / 2);
+                Some((Self::SingleSubst(substitutions), Self::SingleSubst(rest)))
+            }
+            Self::MultipleSubst(mut substitutions) => {
+                if substitutions.len() < 2 { return None; }
+                let rest = substitutions.split_off(substitutions.len() / 2);
+                Some((Self::MultipleSubst(substitutions), Self::MultipleSubst(rest)))
+            }
+            Self::AlternateSubst(mut substitutions) => {
+                if substitutions.len() < 2 { return None; }
+                let rest = substitutions.split_off(substitutions.len() / 2);
+                Some((Self::AlternateSubst(substitutions), Self::AlternateSubst(rest)))
+            }
+            Self::LigatureSubst(mut ligatures) => {
+                if ligatures.len() < 2 { return None; }
+                // Prefer not to split up a ligature set, so its first
+                // glyph isn't covered twice.
+                let mid = ligatures.len() / 2;
+                let idx = (mid..ligatures.len())
+                    .find(|&idx| ligatures[idx - 1].0.first() != ligatures[idx].0.first())
+                    .unwrap_or(mid);
+                let rest = ligatures.split_off(idx);
+                Some((Self::LigatureSubst(ligatures), Self::LigatureSubst(rest)))
+            }
+            // Format 3 subtables hold a single rule.
+            Self::ChainContextSubst(_) => None,
         }
     }
 
     fn write<W: Write>(&self, writer: W) -> io::Result<()> {
         match self {
+            Self::SingleSubst(subs) => {
+                let coverage = Coverage::new(subs.iter().map(|&(glyph, _)| glyph));
+                let delta = |&(GlyphId(glyph), GlyphId(replacement)): &(GlyphId, GlyphId)| {
+                    // deltas are modulo 65536
+                    replacement.wrapping_sub(glyph)
+                };
+                let shared_delta = subs.first().map(delta)
+                    .filter(|first| subs.iter().all(|sub| delta(sub) == *first));
+                if let Some(delta) = shared_delta {
+                    // https://learn.microsoft.com/en-us/typography/opentype/spec/gsub#11-single-substitution-format-1
+                    let mut subtable = SubtableBuffer::new(6);
+                    {
+                        let mut header = subtable.header();
+                        header.write_u16::<BigEndian>(1)?;  // format
+                        header.mark_offset()?;  // offset to coverage table
+                        header.write_u16::<BigEndian>(delta)?;
+                    }
+                    coverage.write(subtable.body())?;
+                    subtable.write(writer)?;
+                } else {
+                    // https://learn.microsoft.com/en-us/typography/opentype/spec/gsub#12-single-substitution-format-2
+                    let mut subtable = SubtableBuffer::new(offset16(6 + 2 * subs.len())?);
+                    {
+                        let mut header = subtable.header();
+                        header.write_u16::<BigEndian>(2)?;  // format
+                        header.mark_offset()?;  // offset to coverage table
+                        header.write_u16::<BigEndian>(count16(subs.len())?)?;
+                        for &(_, GlyphId(replacement)) in subs {
+                            header.write_u16::<BigEndian>(replacement)?;
+                        }
+                    }
+                    coverage.write(subtable.body())?;
+                    subtable.write(writer)?;
+                }
+            }
+            // The two formats share a layout: a sequence of glyphs per covered glyph.
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/gsub#21-multiple-substitution-format-1
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/gsub#31-alternate-substitution-format-1
+            Self::MultipleSubst(subs) | Self::AlternateSubst(subs) => {
+                let mut subtable = SubtableBuffer::new(offset16(6 + 2 * subs.len())?);
+                {
+                    let mut header = subtable.header();
+                    header.write_u16::<BigEndian>(1)?;  // format
+                    header.mark_offset()?;  // offset to coverage table
+                    header.write_u16::<BigEndian>(count16(subs.len())?)?;
+                }
+                Coverage::new(subs.iter().map(|&(glyph, _)| glyph)).write(subtable.body())?;
+                for (_, glyphs) in subs {
+                    subtable.header().mark_offset()?;
+                    let mut body = subtable.body();
+                    body.write_u16::<BigEndian>(count16(glyphs.len())?)?;
+                    for &GlyphId(glyph) in glyphs {
+                        body.write_u16::<BigEndian>(glyph)?;
+                    }
+                }
+                subtable.write(writer)?;
+            }
             // https://learn.microsoft.com/en-us/typography/opentype/spec/gsub#41-ligature-substitution-format-1
             Self::LigatureSubst(subs) => {
                 let ligature_sets: Vec<&[Ligature]> = split_when(
                     subs,
                     |(a, _), (b, _)| a.first() != b.first()
                 ).collect();
-                let ligature_set_count = ligature_sets.len() as u16;
+                let ligature_set_count = count16(ligature_sets.len())?;
                 // offset to coverage table
-                let offset = 6 + SET_RECORD_SIZE * ligature_set_count;
-                let mut subtable = SubtableBuffer::new(offset);
+                let offset = 6 + SET_RECORD_SIZE * ligature_sets.len();
+                let mut subtable = SubtableBuffer::new(offset16(offset)?);
                 {
                     let mut header = subtable.header();
                     header.write_u16::<BigEndian>(1)?;  // version
@@ -161,13 +190,12 @@ impl LookupSubtable {
                     header.write_u16::<BigEndian>(ligature_set_count)?;
                 }
 
-                let coverage = Coverage::List(
+                let coverage = Coverage::new(
                     ligature_sets
                         .iter()
                         .map(|set| *set.first()
                             .and_then(|(pattern, _)| pattern.first())
                             .expect("ligature pattern should not be empty"))
-                        .collect()
                 );
                 coverage.write(&mut subtable.body())?;
 
@@ -177,21 +205,46 @@ impl LookupSubtable {
                 }
                 subtable.write(writer)?;
             }
+            // https://learn.microsoft.com/en-us/typography/opentype/spec/gsub#63-chained-sequence-context-format-3-coverage-based-glyph-contexts
+            Self::ChainContextSubst(rule) => {
+                let coverage_count = rule.backtrack.len() + rule.input.len() + rule.lookahead.len();
+                let offset = 10 + 2 * coverage_count + 4 * rule.lookups.len();
+                let mut subtable = SubtableBuffer::new(offset16(offset)?);
+                subtable.header().write_u16::<BigEndian>(3)?;  // format
+                // The backtrack sequence is stored nearest glyph first.
+                let sequences = [
+                    rule.backtrack.iter().rev().collect::<Vec<_>>(),
+                    rule.input.iter().collect(),
+                    rule.lookahead.iter().collect(),
+                ];
+                for sequence in sequences {
+                    subtable.header().write_u16::<BigEndian>(count16(sequence.len())?)?;
+                    for glyphs in sequence {
+                        subtable.header().mark_offset()?;
+                        Coverage::new(glyphs.iter().copied()).write(subtable.body())?;
+                    }
+                }
+                let mut header = subtable.header();
+                header.write_u16::<BigEndian>(count16(rule.lookups.len())?)?;
+                for &(sequence_idx, LookupId(lookup_idx)) in &rule.lookups {
+                    header.write_u16::<BigEndian>(sequence_idx)?;
+                    header.write_u16::<BigEndian>(lookup_idx)?;
+                }
+                subtable.write(writer)?;
+            }
         }
         Ok(())
     }
 }
 
 fn write_ligature_set<W: Write>(set: &[Ligature], writer: W) -> io::Result<()> {
-    let lig_count = set.len() as u16;
-    let offset = 2 + lig_count * 2;
-    let mut ligset = SubtableBuffer::new(offset);
-    ligset.header().write_u16::<BigEndian>(lig_count)?;
+    let mut ligset = SubtableBuffer::new(offset16(2 + 2 * set.len())?);
+    ligset.header().write_u16::<BigEndian>(count16(set.len())?)?;
     for &(ref pattern, replacement) in set {
         ligset.header().mark_offset()?;  // ligature offset
         let mut body = ligset.body();
         body.write_u16::<BigEndian>(replacement.0)?;  // output glyph id
-        body.write_u16::<BigEndian>(pattern.len() as u16)?;  // component count
+        body.write_u16::<BigEndian>(count16(pattern.len())?)?;  // component count
         // note that we skip the first glyph in the ligature,
         // since it's encoded in the Coverage table
         for &glyph in &pattern[1..] {
@@ -201,130 +254,107 @@ fn write_ligature_set<W: Write>(set: &[Ligature], writer: W) -> io::Result<()> {
     ligset.write(writer)
 }
 
-// ===
-
-enum Coverage {
-    List(Vec<GlyphId>),
-    // Ranges
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl Coverage {
-    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        match self {
-            // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#coverage-format-1
-            Coverage::List(glyph_ids) => {
-                writer.write_u16::<BigEndian>(1)?;  // coverage format
-                writer.write_u16::<BigEndian>(glyph_ids.len() as u16)?;
-                for &GlyphId(id) in glyph_ids {
-                    writer.write_u16::<BigEndian>(id)?;
-                }
-            }
-        }
-        Ok(())
+    fn ligature(pattern: &[u16], replacement: u16) -> Ligature {
+        (pattern.iter().copied().map(GlyphId).collect(), GlyphId(replacement))
     }
-}
-
-// ===
-
-struct ScriptListTable {
-    scripts: Vec<ScriptTable>,
-}
 
-impl ScriptListTable {
-    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#slTbl_sRec
-    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
-        let len = self.scripts.len() as u16;
-        let mut subtable = SubtableBuffer::new(2 + 6 * len);
-        subtable.header().write_u16::<BigEndian>(len)?;
-        for feature in &self.scripts {
-            subtable.header().write_all(&feature.script_tag)?;
-            subtable.header().mark_offset()?;
-            feature.write(subtable.body())?;
-        }
-        subtable.write(writer)
+    #[test]
+    fn longest_ligatures_first() {
+        let subtable = SubstSubtable::ligatures(vec![
+            ligature(&[5, 7], 10),
+            ligature(&[5, 5, 7], 11),
+            ligature(&[3], 12),
+            ligature(&[5, 5], 13),
+        ]);
+        let SubstSubtable::LigatureSubst(ligatures) = subtable else {
+            panic!("expected a ligature subtable");
+        };
+        assert_eq!(ligatures, vec![
+            ligature(&[3], 12),
+            ligature(&[5, 5, 7], 11),
+            ligature(&[5, 7], 10),
+            ligature(&[5, 5], 13),
+        ]);
     }
-}
-
-struct ScriptTable {
-    script_tag: [u8; 4],
-    default: Option<LangSysTable>,
-    lang_sys: Vec<([u8; 4], LangSysTable)>,
-}
 
-impl ScriptTable {
-    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#script-table-and-language-system-record
-    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
-        let len = self.lang_sys.len() as u16;
-        let mut subtable = SubtableBuffer::new(4 + 6 * len);
-        if let Some(lang_sys) = &self.default {
-            subtable.header().mark_offset()?;
-            lang_sys.write(subtable.body())?;
-        } else {
-            subtable.header().write_u16::<BigEndian>(0)?;  // null
-        }
-        subtable.header().write_u16::<BigEndian>(len)?;
-        for (tag, ls) in &self.lang_sys {
-            subtable.header().write_all(tag)?;
-            subtable.header().mark_offset()?;
-            ls.write(subtable.body())?;
-        }
-        subtable.write(writer)
+    #[test]
+    fn chain_context_backtrack_nearest_first() {
+        let rule = ChainRule {
+            backtrack: vec![vec![GlyphId(1)], vec![GlyphId(2)]],
+            input: vec![vec![GlyphId(3)]],
+            lookahead: Vec::new(),
+            lookups: vec![(0, LookupId(4))],
+        };
+        let mut buf = Vec::new();
+        SubstSubtable::ChainContextSubst(rule).write(&mut buf).unwrap();
+        assert_eq!(buf, [
+            0, 3,  // format
+            0, 2, 0, 20, 0, 26,  // backtrack coverages, nearest first
+            0, 1, 0, 32,  // input coverages
+            0, 0,  // lookahead coverages
+            0, 1, 0, 0, 0, 4,  // lookup records
+            0, 1, 0, 1, 0, 2,  // coverage of glyph 2
+            0, 1, 0, 1, 0, 1,  // coverage of glyph 1
+            0, 1, 0, 1, 0, 3,  // coverage of glyph 3
+        ]);
     }
-}
 
-struct LangSysTable {
-    reqd_feature_idx: u16,
-    feature_list_indices: Vec<u16>,
-}
-
-impl LangSysTable {
-    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#language-system-table
-    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        writer.write_u16::<BigEndian>(0)?;  // reserved null byte
-        writer.write_u16::<BigEndian>(self.reqd_feature_idx)?;
-        writer.write_u16::<BigEndian>(self.feature_list_indices.len() as u16)?;
-        for &idx in &self.feature_list_indices {
-            writer.write_u16::<BigEndian>(idx)?;
-        }
-        Ok(())
+    fn sequences(subs: &[(u16, &[u16])]) -> Vec<(GlyphId, Vec<GlyphId>)> {
+        subs.iter().map(|&(glyph, glyphs)| (GlyphId(glyph), glyphs.iter().copied().map(GlyphId).collect())).collect()
     }
-}
 
-struct FeatureListTable {
-    features: Vec<FeatureTable>,
-}
+    #[test]
+    fn multiple_subst_sequence_per_covered_glyph() {
+        let subtable = SubstSubtable::multiple(sequences(&[(5, &[1, 2]), (3, &[4])]));
+        assert_eq!(subtable.lookup_type(), 2);
+        let mut buf = Vec::new();
+        subtable.write(&mut buf).unwrap();
+        assert_eq!(buf, [
+            0, 1,  // format
+            0, 10,  // coverage offset
+            0, 2, 0, 18, 0, 22,  // sequence offsets, by glyph
+            0, 1, 0, 2, 0, 3, 0, 5,  // coverage of glyphs 3 and 5
+            0, 1, 0, 4,  // glyph 3's sequence
+            0, 2, 0, 1, 0, 2,  // glyph 5's sequence
+        ]);
+    }
 
-impl FeatureListTable {
-    // https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#flTbl
-    fn write<W: Write>(&self, writer: W) -> io::Result<()> {
-        let len = self.features.len() as u16;
-        let mut subtable = SubtableBuffer::new(2 + 6 * len);
-        subtable.header().write_u16::<BigEndian>(len)?;
-        for feature in &self.features {
-            subtable.header().write_all(&feature.tag)?;
-            subtable.header().mark_offset()?;
-            feature.write(subtable.body())?;
-        }
-        subtable.write(writer)
+    #[test]
+    fn alternate_subst_keeps_the_alternates_order() {
+        let subtable = SubstSubtable::alternates(sequences(&[(5, &[9, 8])]));
+        assert_eq!(subtable.lookup_type(), 3);
+        let mut buf = Vec::new();
+        subtable.write(&mut buf).unwrap();
+        assert_eq!(buf, [
+            0, 1,  // format
+            0, 8,  // coverage offset
+            0, 1, 0, 14,  // alternate set offsets
+            0, 1, 0, 1, 0, 5,  // coverage of glyph 5
+            0, 2, 0, 9, 0, 8,  // glyph 5's alternates
+        ]);
     }
-}
 
-struct FeatureTable {
-    tag: [u8; 4],  // 4-byte feature identification tag
-    lookup_list_indices: Vec<u16>, // Array of indices into the LookupList — zero-based (first lookup is LookupListIndex = 0)
-}
+    #[test]
+    fn sequence_subtables_split_between_glyphs() {
+        let subs = sequences(&[(1, &[4, 5]), (2, &[6]), (3, &[7, 8])]);
+        let Some((SubstSubtable::AlternateSubst(first), SubstSubtable::AlternateSubst(rest))) =
+            SubstSubtable::alternates(subs.clone()).split() else { panic!("expected two alternate subtables") };
+        assert_eq!((first, rest), (subs[..1].to_vec(), subs[1..].to_vec()));
+        let Some((SubstSubtable::MultipleSubst(first), SubstSubtable::MultipleSubst(rest))) =
+            SubstSubtable::multiple(subs.clone()).split() else { panic!("expected two multiple subtables") };
+        assert_eq!((first, rest), (subs[..1].to_vec(), subs[1..].to_vec()));
+        assert!(SubstSubtable::multiple(subs[..1].to_vec()).split().is_none());
+    }
 
-impl FeatureTable {
-    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        // > Offset from start of Feature table to FeatureParams table,
-        // > if defined for the feature and present, else NULL.
-        // The FeatureParams table doesn't appear to be documented anywhere?????
-        writer.write_u16::<BigEndian>(0)?;
-        let len = self.lookup_list_indices.len() as u16;
-        writer.write_u16::<BigEndian>(len)?;
-        for &idx in &self.lookup_list_indices {
-            writer.write_u16::<BigEndian>(idx)?;
-        }
-        Ok(())
+    #[test]
+    fn too_many_ligature_sets_overflow() {
+        // Past 32765 sets, the set offsets push the coverage table out
+        // of reach of its 16-bit offset.
+        let subtable = SubstSubtable::ligatures((0..40000).map(|glyph| ligature(&[glyph, 1], 2)).collect());
+        assert!(!crate::subtable::fits(subtable.write(io::sink())));
     }
 }
@@ -12,10 +12,11 @@ pub(crate) struct Head {
     font_revision: u32,
     checksum_adjustment: u32,
     flags: Flags,
-    units_per_em: u16,
+    pub units_per_em: u16,
     created: time::DateTime,
     modified: time::DateTime,
-    rect: Rect,
+    /// The union of every glyph's bounding box.
+    pub rect: Rect,
     mac_style: MacStyle,
     lowest_rec_ppem: u16,  // smallest readable size in pixels
     font_direction_hint: i16,
@@ -26,7 +27,7 @@ pub(crate) struct Head {
 impl Head {
     pub(crate) fn new() -> Self {
         let now = time::now();
-        // XXX: should this be autogenerated???????
+        // The cell, until the font's glyphs are measured.
         let rect = Rect {
             x_min: 0,
             y_min: 0,
@@ -3,13 +3,13 @@ use crate::{FontTable, TableWriter};
 use std::io::{self, Write};
 
 pub(crate) struct HHea {
-    ascent: i16,  //  Distance from baseline of highest ascender
-    descent: i16,  // Distance from baseline of lowest descender
+    pub ascent: i16,  //  Distance from baseline of highest ascender
+    pub descent: i16,  // Distance from baseline of lowest descender
     line_gap: i16,  // typographic line gap
-    advance_width_max: u16,  // must be consistent with horizontal metrics
-    min_left_side_bearing: i16,  // must be consistent with horizontal metrics
-    min_right_side_bearing: i16,  // must be consistent with horizontal metrics
-    x_max_extent: i16,  // max(lsb + (xMax-xMin))
+    pub advance_width_max: u16,  // must be consistent with horizontal metrics
+    pub min_left_side_bearing: i16,  // must be consistent with horizontal metrics
+    pub min_right_side_bearing: i16,  // must be consistent with horizontal metrics
+    pub x_max_extent: i16,  // max(lsb + (xMax-xMin))
     caret_slope_rise: i16,  // used to calculate the slope of the caret (rise/run) set to 1 for vertical caret
     caret_slope_run: i16,  // 0 for vertical
     caret_offset: i16,  // set value to 0 for non-slanted fonts
@@ -1,5 +1,7 @@
 // https://developer.apple.com/fonts/TrueType-Reference-Manual/RM06/Chap6hmtx.html
 use crate::{FontTable, TableWriter};
+#[cfg(test)]
+use crate::GlyphId;
 use std::io::{self, Write};
 use byteorder::{BigEndian, WriteBytesExt};
 
@@ -8,9 +10,10 @@ pub(crate) struct HMtx {
 }
 
 impl HMtx {
-    pub fn monospace(advance_width: u16, lsbs: Vec<i16>) -> Self {
+    /// Build the table from each glyph's advance width and left side bearing.
+    pub fn from_metrics<I: IntoIterator<Item=(u16, i16)>>(metrics: I) -> Self {
         let horizontal_metrics: Vec<_> =
-            lsbs.into_iter().map(|left_side_bearing|
+            metrics.into_iter().map(|(advance_width, left_side_bearing)|
                 HorizontalMetric { advance_width, left_side_bearing }
             ).collect();
         HMtx { horizontal_metrics }
@@ -24,6 +27,18 @@ impl HMtx {
             .rposition(|hmtx| hmtx.advance_width != advance_width)
             .map_or(1, |x| x + 2)
     }
+
+    /// The glyph's advance width.
+    #[cfg(test)]
+    pub fn advance_width(&self, glyph: GlyphId) -> u16 {
+        self.horizontal_metrics[glyph.0 as usize].advance_width
+    }
+
+    /// The glyph's left side bearing.
+    #[cfg(test)]
+    pub fn left_side_bearing(&self, glyph: GlyphId) -> i16 {
+        self.horizontal_metrics[glyph.0 as usize].left_side_bearing
+    }
 }
 
 impl FontTable for HMtx {
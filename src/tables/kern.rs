@@ -0,0 +1,88 @@
+// https://learn.microsoft.com/en-us/typography/opentype/spec/kern
+// https://developer.apple.com/fonts/TrueType-Reference-Manual/RM06/Chap6kern.html
+
+use byteorder::{BigEndian, WriteBytesExt};
+use crate::{FontTable, GlyphId, TableWriter};
+use crate::bsearch::BSearch;
+use std::io::{self, Write};
+
+const HEADER_SIZE: usize = 14;
+const PAIR_SIZE: usize = 6;
+/// The most pairs which fit in a subtable, whose length is only 16 bits.
+const MAX_PAIRS: usize = (u16::MAX as usize - HEADER_SIZE) / PAIR_SIZE;
+
+/// The legacy kerning table, for renderers which don't read GPOS.
+pub(crate) struct Kern {
+    /// Each pair of glyphs and the adjustment to the advance of the
+    /// first, sorted by pair.
+    pairs: Vec<(GlyphId, GlyphId, i16)>,
+}
+
+impl Kern {
+    /// Build the table from kerning pairs. If a pair is given more than
+    /// once, the last value wins. Returns `None` if there's nothing to kern.
+    pub fn new<I: IntoIterator<Item=(GlyphId, GlyphId, i16)>>(pairs: I) -> Option<Self> {
+        let mut pairs: Vec<_> = pairs.into_iter().collect();
+        // The stable sort keeps repeated pairs in the order they were given.
+        pairs.sort_by_key(|&(left, right, _)| (left, right));
+        pairs.reverse();
+        pairs.dedup_by_key(|&mut (left, right, _)| (left, right));
+        pairs.reverse();
+        pairs.retain(|&(_, _, value)| value != 0);
+        (!pairs.is_empty()).then_some(Kern { pairs })
+    }
+}
+
+impl FontTable for Kern {
+    const TAG: &'static [u8; 4] = b"kern";
+
+    fn write<W: Write>(&self, writer: &mut TableWriter<W>) -> io::Result<()> {
+        // Subtables' values are summed, so pairs may be split among them.
+        let chunks = self.pairs.chunks(MAX_PAIRS);
+        writer.write_u16::<BigEndian>(0)?;  // version
+        writer.write_u16::<BigEndian>(chunks.len() as u16)?;
+        for pairs in chunks {
+            let len = pairs.len() as u16;
+            let bsearch = BSearch::from(len, PAIR_SIZE as u16);
+            writer.write_u16::<BigEndian>(0)?;  // version
+            writer.write_u16::<BigEndian>(HEADER_SIZE as u16 + bsearch.len)?;
+            writer.write_u16::<BigEndian>(0x0001)?;  // coverage: horizontal, format 0
+            writer.write_u16::<BigEndian>(len)?;
+            writer.write_u16::<BigEndian>(bsearch.search_range)?;
+            writer.write_u16::<BigEndian>(bsearch.entry_selector)?;
+            writer.write_u16::<BigEndian>(bsearch.range_shift)?;
+            for &(left, right, value) in pairs {
+                writer.write_u16::<BigEndian>(left.0)?;
+                writer.write_u16::<BigEndian>(right.0)?;
+                writer.write_i16::<BigEndian>(value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_sorted_and_last_value_wins() {
+        let kern = Kern::new([
+            (GlyphId(5), GlyphId(2), -1),
+            (GlyphId(3), GlyphId(9), -2),
+            (GlyphId(5), GlyphId(2), 1),
+            (GlyphId(3), GlyphId(4), 0),
+            (GlyphId(3), GlyphId(7), -3),
+        ]).unwrap();
+        assert_eq!(kern.pairs, [
+            (GlyphId(3), GlyphId(7), -3),
+            (GlyphId(3), GlyphId(9), -2),
+            (GlyphId(5), GlyphId(2), 1),
+        ]);
+    }
+
+    #[test]
+    fn no_pairs_no_table() {
+        assert!(Kern::new([(GlyphId(3), GlyphId(4), 0)]).is_none());
+    }
+}
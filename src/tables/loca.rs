@@ -1,5 +1,7 @@
 // https://developer.apple.com/fonts/TrueType-Reference-Manual/RM06/Chap6loca.html
 use crate::{FontTable, TableWriter};
+#[cfg(test)]
+use crate::GlyphId;
 use std::io::{self, Write};
 use byteorder::{BigEndian, WriteBytesExt};
 
@@ -12,6 +14,12 @@ impl Loca {
         Self { offsets }
     }
 
+    /// The length of the glyph's data in the `glyf` table.
+    #[cfg(test)]
+    pub fn len(&self, glyph: GlyphId) -> usize {
+        self.offsets[glyph.0 as usize + 1] - self.offsets[glyph.0 as usize]
+    }
+
     pub fn needs_long(&self) -> bool {
         let longest = u16::MAX as usize;
         matches!(self.offsets.last(), Some(&idx) if idx > longest)
@@ -1,21 +1,29 @@
 mod cmap;
+mod common;
+mod gdef;
 mod glyf;
+mod gpos;
 mod gsub;
 mod head;
 mod hhea;
 mod hmtx;
+mod kern;
 mod loca;
 mod maxp;
 pub(crate) mod name;
 mod os2;
-mod post;
+pub(crate) mod post;
 
 pub(crate) use cmap::CMap;
-pub(crate) use glyf::Glyf;
-pub(crate) use gsub::GSub;
+pub(crate) use common::{FeatureParams, LayoutBuilder, LookupFlags, LookupId, LookupSubtable};
+pub(crate) use gdef::{GDef, GlyphClass};
+pub(crate) use glyf::{Component, Glyf, Glyph};
+pub(crate) use gpos::{CursiveConnection, GPos, GPosBuilder, MarkAttachment, PairClasses, PosSubtable};
+pub(crate) use gsub::{ChainRule, GSub, GSubBuilder, Ligature, SubstSubtable};
 pub(crate) use head::Head;
 pub(crate) use hhea::HHea;
 pub(crate) use hmtx::HMtx;
+pub(crate) use kern::Kern;
 pub(crate) use loca::Loca;
 pub(crate) use maxp::MaxP;
 pub(crate) use name::Name;
@@ -27,6 +27,15 @@ impl Name {
             text: text.as_ref().to_string(),
         });
     }
+
+    /// The text of each record with `name_id`, one per platform.
+    #[cfg(test)]
+    pub fn texts(&self, name_id: u16) -> Vec<&str> {
+        self.name_records.iter()
+            .filter(|record| record.name_id == name_id)
+            .map(|record| record.text.as_str())
+            .collect()
+    }
 }
 
 impl FontTable for Name {
@@ -93,6 +102,8 @@ pub const FULL_FONT_NAME: u16 = 4;
 pub const NAME_TABLE_VERSION: u16 = 5;
 pub const POSTSCRIPT_NAME: u16 = 6;
 pub const DESCRIPTION: u16 = 10;
+// IDs from here on are free for the font's own use, eg feature names.
+pub const FIRST_FONT_SPECIFIC: u16 = 256;
 
 // Microsoft Languages
 pub const ENGLISH_UNITEDSTATES: u16 =  0x0409;
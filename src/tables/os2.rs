@@ -50,35 +50,37 @@ impl Default for Os2 {
 }
 
 pub(crate) struct Os2V5 {
-    x_avg_char_width: i16,
+    /// The average advance of the glyphs which advance at all.
+    pub x_avg_char_width: i16,
     us_weight_class: u16,
     us_width_class: u16,
     fs_type: u16,
-    y_subscript_x_size: i16,
-    y_subscript_y_size: i16,
-    y_subscript_x_offset: i16,
-    y_subscript_y_offset: i16,
-    y_superscript_x_size: i16,
-    y_superscript_y_size: i16,
-    y_superscript_x_offset: i16,
-    y_superscript_y_offset: i16,
+    pub y_subscript_x_size: i16,
+    pub y_subscript_y_size: i16,
+    pub y_subscript_x_offset: i16,
+    pub y_subscript_y_offset: i16,
+    pub y_superscript_x_size: i16,
+    pub y_superscript_y_size: i16,
+    pub y_superscript_x_offset: i16,
+    pub y_superscript_y_offset: i16,
     y_strikeout_size: i16,
     y_strikeout_position: i16,
     s_family_class: i16,
-    panose: [u8; 10],
+    /// The PANOSE classification: all "any", unless the font is monospaced.
+    pub panose: [u8; 10],
     ul_unicode_range: u128,
     ach_vend_id: [u8; 4],
     fs_selection: u16,
     us_first_char_index: u16,
     us_last_char_index: u16,
-    s_typo_ascender: i16,
-    s_typo_descender: i16,
+    pub s_typo_ascender: i16,
+    pub s_typo_descender: i16,
     s_typo_line_gap: i16,
-    us_win_ascent: u16,
-    us_win_descent: u16,
+    pub us_win_ascent: u16,
+    pub us_win_descent: u16,
     ul_code_page_range: u64,
-    sx_height: i16,
-    s_cap_height: i16,
+    pub sx_height: i16,
+    pub s_cap_height: i16,
     us_default_char: u16,
     us_break_char: u16,
     us_max_context: u16,
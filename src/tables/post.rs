@@ -1,11 +1,8 @@
 // https://developer.apple.com/fonts/TrueType-Reference-Manual/RM06/Chap6post.html
 use crate::{FontTable, GlyphId, TableWriter};
 use std::io::{self, Write};
-use std::iter::repeat;
 use byteorder::{BigEndian, WriteBytesExt};
 
-const NOT_DEF: GlyphId = GlyphId(0);
-
 pub(crate) struct Post {
     italic_angle: u32,
     underline_position: i16,
@@ -19,25 +16,25 @@ pub(crate) struct Post {
 }
 
 impl Post {
-    pub fn from_ascii_order(order: &[char], count: usize) -> Self {
-        let diff = count.checked_sub(order.len()+1)
-            .expect("count must be > order.len()");
-        let glyphs = order.into_iter()
-            .map(|&c| to_macintosh(c).unwrap_or(NOT_DEF));
-        let names: Vec<_> = [NOT_DEF].into_iter().chain(glyphs)
-            .chain(repeat(NOT_DEF).take(diff))
-            .map(GlyphName::Preset)
-            .collect();
-        assert_eq!(names.len(), count);
+    /// Name each glyph in the font, in glyph order, noting whether
+    /// they all advance the same distance.
+    pub fn from_names<S: AsRef<str>>(names: &[S], is_monospace: bool) -> Self {
+        let names = names.iter().map(|name| {
+            let name = name.as_ref();
+            match MACINTOSH_GLYPHS.iter().position(|&(_, standard)| standard == name) {
+                Some(idx) => GlyphName::Preset(GlyphId(idx as u16)),
+                None => GlyphName::Custom(name.to_string()),
+            }
+        }).collect();
         let format = PostFormat::Format2 { names };
 
-        // TODO: don't hardcode the first four here.
+        // TODO: don't hardcode the first three here.
         // the mem fields seem optional
         Post {
             italic_angle: 0,
             underline_position: 0,
             underline_thickness: 1,
-            is_monospace: true,
+            is_monospace,
             min_mem_type42: 0,
             max_mem_type42: 0,
             min_mem_type1: 0,
@@ -45,6 +42,21 @@ impl Post {
             format,
         }
     }
+
+    /// Whether every glyph advances the same distance.
+    #[cfg(test)]
+    pub fn is_monospace(&self) -> bool {
+        self.is_monospace
+    }
+
+    /// The name of each glyph in the font, in glyph order.
+    pub fn glyph_names(&self) -> Vec<String> {
+        let PostFormat::Format2 { names } = &self.format;
+        names.iter().map(|name| match name {
+            GlyphName::Preset(GlyphId(idx)) => MACINTOSH_GLYPHS[*idx as usize].1.to_string(),
+            GlyphName::Custom(name) => name.clone(),
+        }).collect()
+    }
 }
 
 impl FontTable for Post {
@@ -95,6 +107,7 @@ impl PostFormat {
                             writer.write_u16::<BigEndian>(p.0)?,
                         GlyphName::Custom(s) => {
                             writer.write_u16::<BigEndian>(str_offset)?;
+                            str_offset += 1;
                             string_bytes.extend(to_pascal_string(s));
                         }
                     }
@@ -106,29 +119,150 @@ impl PostFormat {
     }
 }
 
-// the docs just say "Pascal string".
-// gonna assume this is what they mean
+// Format 2 names are stored as Pascal strings: a length byte, followed
+// by that many bytes of (ASCII) name.
 fn to_pascal_string(s: &str) -> Vec<u8> {
-    let string: Vec<u16> = s.encode_utf16().collect();
-    let len = string.len() as u16;
-    let mut out = Vec::new();
-    out.extend(len.to_be_bytes());
-    for c in string {
-        out.extend(c.to_be_bytes());
-    }
+    let mut out = vec![s.len() as u8];
+    out.extend(s.bytes());
     out
 }
 
-fn to_macintosh(c: char) -> Option<GlyphId> {
-    let lookup =
-        " !\"#$%&'()*+,-./0123456789:;<=>?@\
-        ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`\
-        abcdefghijklmnopqrstuvwxyz{|}~\
-        ÄÅÇÉÑÖÜáàâäãåçéèêëíìîïñóòôöõúùûü\
-        †˚¢£§•¶ß®©™´¨≠åÆØ∞±≤≥¥µ∂∑∏π∫ªºΩæø\
-        ¿¡¬√ƒ≈Δ«»… ÁÃÕŒœ–—“”‘’÷◊ÿŸ⁄¤‹›ﬁﬂ\
-        ‡·‚„‰ÂÊÁËÈÍÎÏÌÓÔÒÚÛÙı";
-    lookup.chars()
-        .position(|x| x == c)
-        .map(|x| GlyphId(x as u16 + 3))
+/// The PostScript name of the glyph for `c`: its standard Macintosh
+/// name if it has one, otherwise `uniXXXX` (or `uXXXXX` outside the BMP).
+pub(crate) fn glyph_name(c: char) -> String {
+    match MACINTOSH_GLYPHS.iter().find(|(chr, _)| *chr == Some(c)) {
+        Some((_, name)) => name.to_string(),
+        None if (c as u32) <= 0xFFFF => format!("uni{:04X}", c as u32),
+        None => format!("u{:05X}", c as u32),
+    }
+}
+
+/// Whether `name` is a valid PostScript glyph name: at most 63 of
+/// `A-Z`, `a-z`, `0-9`, `.` and `_`, not starting with a digit.
+/// `.notdef` is the only name which may start with a period.
+pub(crate) fn is_valid_glyph_name(name: &str) -> bool {
+    let valid_chars = name.bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_');
+    let valid_start = !name.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        || name == ".notdef";
+    !name.is_empty() && name.len() <= 63 && valid_chars && valid_start
+}
+
+// The 258 standard Macintosh glyph names, and the character each
+// conventionally represents (the first three are control glyphs).
+// Format 2 can refer to these by index rather than spelling them out.
+const MACINTOSH_GLYPHS: [(Option<char>, &str); 258] = [
+    (None, ".notdef"), (None, ".null"), (None, "nonmarkingreturn"), (Some(' '), "space"),
+    (Some('!'), "exclam"), (Some('"'), "quotedbl"), (Some('#'), "numbersign"),
+    (Some('$'), "dollar"), (Some('%'), "percent"), (Some('&'), "ampersand"),
+    (Some('\''), "quotesingle"), (Some('('), "parenleft"), (Some(')'), "parenright"),
+    (Some('*'), "asterisk"), (Some('+'), "plus"), (Some(','), "comma"), (Some('-'), "hyphen"),
+    (Some('.'), "period"), (Some('/'), "slash"), (Some('0'), "zero"), (Some('1'), "one"),
+    (Some('2'), "two"), (Some('3'), "three"), (Some('4'), "four"), (Some('5'), "five"),
+    (Some('6'), "six"), (Some('7'), "seven"), (Some('8'), "eight"), (Some('9'), "nine"),
+    (Some(':'), "colon"), (Some(';'), "semicolon"), (Some('<'), "less"), (Some('='), "equal"),
+    (Some('>'), "greater"), (Some('?'), "question"), (Some('@'), "at"), (Some('A'), "A"),
+    (Some('B'), "B"), (Some('C'), "C"), (Some('D'), "D"), (Some('E'), "E"), (Some('F'), "F"),
+    (Some('G'), "G"), (Some('H'), "H"), (Some('I'), "I"), (Some('J'), "J"), (Some('K'), "K"),
+    (Some('L'), "L"), (Some('M'), "M"), (Some('N'), "N"), (Some('O'), "O"), (Some('P'), "P"),
+    (Some('Q'), "Q"), (Some('R'), "R"), (Some('S'), "S"), (Some('T'), "T"), (Some('U'), "U"),
+    (Some('V'), "V"), (Some('W'), "W"), (Some('X'), "X"), (Some('Y'), "Y"), (Some('Z'), "Z"),
+    (Some('['), "bracketleft"), (Some('\\'), "backslash"), (Some(']'), "bracketright"),
+    (Some('^'), "asciicircum"), (Some('_'), "underscore"), (Some('`'), "grave"),
+    (Some('a'), "a"), (Some('b'), "b"), (Some('c'), "c"), (Some('d'), "d"), (Some('e'), "e"),
+    (Some('f'), "f"), (Some('g'), "g"), (Some('h'), "h"), (Some('i'), "i"), (Some('j'), "j"),
+    (Some('k'), "k"), (Some('l'), "l"), (Some('m'), "m"), (Some('n'), "n"), (Some('o'), "o"),
+    (Some('p'), "p"), (Some('q'), "q"), (Some('r'), "r"), (Some('s'), "s"), (Some('t'), "t"),
+    (Some('u'), "u"), (Some('v'), "v"), (Some('w'), "w"), (Some('x'), "x"), (Some('y'), "y"),
+    (Some('z'), "z"), (Some('{'), "braceleft"), (Some('|'), "bar"), (Some('}'), "braceright"),
+    (Some('~'), "asciitilde"), (Some('Ä'), "Adieresis"), (Some('Å'), "Aring"),
+    (Some('Ç'), "Ccedilla"), (Some('É'), "Eacute"), (Some('Ñ'), "Ntilde"),
+    (Some('Ö'), "Odieresis"), (Some('Ü'), "Udieresis"), (Some('á'), "aacute"),
+    (Some('à'), "agrave"), (Some('â'), "acircumflex"), (Some('ä'), "adieresis"),
+    (Some('ã'), "atilde"), (Some('å'), "aring"), (Some('ç'), "ccedilla"),
+    (Some('é'), "eacute"), (Some('è'), "egrave"), (Some('ê'), "ecircumflex"),
+    (Some('ë'), "edieresis"), (Some('í'), "iacute"), (Some('ì'), "igrave"),
+    (Some('î'), "icircumflex"), (Some('ï'), "idieresis"), (Some('ñ'), "ntilde"),
+    (Some('ó'), "oacute"), (Some('ò'), "ograve"), (Some('ô'), "ocircumflex"),
+    (Some('ö'), "odieresis"), (Some('õ'), "otilde"), (Some('ú'), "uacute"),
+    (Some('ù'), "ugrave"), (Some('û'), "ucircumflex"), (Some('ü'), "udieresis"),
+    (Some('†'), "dagger"), (Some('°'), "degree"), (Some('¢'), "cent"),
+    (Some('£'), "sterling"), (Some('§'), "section"), (Some('•'), "bullet"),
+    (Some('¶'), "paragraph"), (Some('ß'), "germandbls"), (Some('®'), "registered"),
+    (Some('©'), "copyright"), (Some('™'), "trademark"), (Some('´'), "acute"),
+    (Some('¨'), "dieresis"), (Some('≠'), "notequal"), (Some('Æ'), "AE"),
+    (Some('Ø'), "Oslash"), (Some('∞'), "infinity"), (Some('±'), "plusminus"),
+    (Some('≤'), "lessequal"), (Some('≥'), "greaterequal"), (Some('¥'), "yen"),
+    (Some('µ'), "mu"), (Some('∂'), "partialdiff"), (Some('∑'), "summation"),
+    (Some('∏'), "product"), (Some('π'), "pi"), (Some('∫'), "integral"),
+    (Some('ª'), "ordfeminine"), (Some('º'), "ordmasculine"), (Some('Ω'), "Omega"),
+    (Some('æ'), "ae"), (Some('ø'), "oslash"), (Some('¿'), "questiondown"),
+    (Some('¡'), "exclamdown"), (Some('¬'), "logicalnot"), (Some('√'), "radical"),
+    (Some('ƒ'), "florin"), (Some('≈'), "approxequal"), (Some('∆'), "Delta"),
+    (Some('«'), "guillemotleft"), (Some('»'), "guillemotright"),
+    (Some('…'), "ellipsis"), (Some('\u{00A0}'), "nonbreakingspace"), (Some('À'), "Agrave"),
+    (Some('Ã'), "Atilde"), (Some('Õ'), "Otilde"), (Some('Œ'), "OE"),
+    (Some('œ'), "oe"), (Some('–'), "endash"), (Some('—'), "emdash"),
+    (Some('“'), "quotedblleft"), (Some('”'), "quotedblright"),
+    (Some('‘'), "quoteleft"), (Some('’'), "quoteright"), (Some('÷'), "divide"),
+    (Some('◊'), "lozenge"), (Some('ÿ'), "ydieresis"), (Some('Ÿ'), "Ydieresis"),
+    (Some('⁄'), "fraction"), (Some('¤'), "currency"), (Some('‹'), "guilsinglleft"),
+    (Some('›'), "guilsinglright"), (Some('ﬁ'), "fi"), (Some('ﬂ'), "fl"),
+    (Some('‡'), "daggerdbl"), (Some('·'), "periodcentered"), (Some('‚'), "quotesinglbase"),
+    (Some('„'), "quotedblbase"), (Some('‰'), "perthousand"), (Some('Â'), "Acircumflex"),
+    (Some('Ê'), "Ecircumflex"), (Some('Á'), "Aacute"), (Some('Ë'), "Edieresis"),
+    (Some('È'), "Egrave"), (Some('Í'), "Iacute"), (Some('Î'), "Icircumflex"),
+    (Some('Ï'), "Idieresis"), (Some('Ì'), "Igrave"), (Some('Ó'), "Oacute"),
+    (Some('Ô'), "Ocircumflex"), (Some('\u{F8FF}'), "apple"), (Some('Ò'), "Ograve"),
+    (Some('Ú'), "Uacute"), (Some('Û'), "Ucircumflex"), (Some('Ù'), "Ugrave"),
+    (Some('ı'), "dotlessi"), (Some('ˆ'), "circumflex"), (Some('˜'), "tilde"),
+    (Some('¯'), "macron"), (Some('˘'), "breve"), (Some('˙'), "dotaccent"),
+    (Some('˚'), "ring"), (Some('¸'), "cedilla"), (Some('˝'), "hungarumlaut"),
+    (Some('˛'), "ogonek"), (Some('ˇ'), "caron"), (Some('Ł'), "Lslash"),
+    (Some('ł'), "lslash"), (Some('Š'), "Scaron"), (Some('š'), "scaron"),
+    (Some('Ž'), "Zcaron"), (Some('ž'), "zcaron"), (Some('¦'), "brokenbar"),
+    (Some('Ð'), "Eth"), (Some('ð'), "eth"), (Some('Ý'), "Yacute"),
+    (Some('ý'), "yacute"), (Some('Þ'), "Thorn"), (Some('þ'), "thorn"),
+    (Some('−'), "minus"), (Some('×'), "multiply"), (Some('¹'), "onesuperior"),
+    (Some('²'), "twosuperior"), (Some('³'), "threesuperior"), (Some('½'), "onehalf"),
+    (Some('¼'), "onequarter"), (Some('¾'), "threequarters"), (Some('₣'), "franc"),
+    (Some('Ğ'), "Gbreve"), (Some('ğ'), "gbreve"), (Some('İ'), "Idotaccent"),
+    (Some('Ş'), "Scedilla"), (Some('ş'), "scedilla"), (Some('Ć'), "Cacute"),
+    (Some('ć'), "cacute"), (Some('Č'), "Ccaron"), (Some('č'), "ccaron"),
+    (Some('đ'), "dcroat"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read back the glyph names of format 2 `data`.
+    fn read_names(data: &[u8]) -> Vec<String> {
+        let u16_at = |idx: usize| u16::from_be_bytes([data[idx], data[idx + 1]]);
+        let count = u16_at(0) as usize;
+        let mut strings = Vec::new();
+        let mut rest = &data[2 + 2 * count..];
+        while let [len, tail @ ..] = rest {
+            let (name, tail) = tail.split_at(*len as usize);
+            strings.push(String::from_utf8(name.to_vec()).unwrap());
+            rest = tail;
+        }
+        (0..count).map(|glyph| match u16_at(2 + 2 * glyph) as usize {
+            idx if idx < 258 => MACINTOSH_GLYPHS[idx].1.to_string(),
+            idx => strings[idx - 258].clone(),
+        }).collect()
+    }
+
+    #[test]
+    fn custom_names_follow_the_macintosh_names() {
+        let names = [".notdef", "a", "f_i", "space", "a.sc", "uni0301"];
+        let post = Post::from_names(&names, false);
+        let mut data = Vec::new();
+        post.format.write(&mut data).unwrap();
+        assert_eq!(read_names(&data), names);
+        // Each custom name gets the next index past the Macintosh names.
+        let indices: Vec<u16> = data[2..14].chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(indices[2..3], [258]);
+        assert_eq!(indices[4..], [259, 260]);
+    }
 }
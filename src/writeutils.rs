@@ -142,10 +142,6 @@ impl<W: Write> CountWriter<W> {
     pub fn count(&self) -> usize {
         self.count
     }
-
-    pub fn into_inner(self) -> W {
-        self.writer
-    }
 }
 
 impl CountWriter<io::Sink> {